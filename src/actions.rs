@@ -0,0 +1,367 @@
+//! Notification actions and control commands, received from background threads
+//! and dispatched to the main loop
+
+use std::{
+    collections::VecDeque,
+    io::Write as _,
+    process::{Command, Stdio},
+    sync::{Arc, Mutex, PoisonError, mpsc},
+    thread,
+};
+
+#[cfg(all(unix, not(target_os = "macos")))]
+use crate::backend;
+use crate::{config, control, gotify};
+
+/// Maximum number of notifications watched for actions, the oldest are forgotten
+pub(crate) const MAX_WATCHED: usize = 100;
+
+/// Channel to send back the result of a control command
+pub(crate) type Reply = mpsc::Sender<Result<(), String>>;
+
+/// Event from a background thread
+#[derive(Debug)]
+enum Event {
+    /// Action invoked, with notification id and action key
+    #[cfg_attr(
+        all(target_os = "macos", not(test)),
+        expect(dead_code, reason = "Only XDG notification servers support actions")
+    )]
+    Invoked(u32, String),
+    /// Notification closed, with notification id
+    #[cfg_attr(
+        all(target_os = "macos", not(test)),
+        expect(dead_code, reason = "Only XDG notification servers support actions")
+    )]
+    Closed(u32),
+    /// Notification server appeared, or is gone
+    #[cfg_attr(
+        all(target_os = "macos", not(test)),
+        expect(dead_code, reason = "Only XDG notification servers can be watched")
+    )]
+    ServerChanged(bool),
+    /// Control command
+    Command(control::Command, Reply),
+}
+
+/// Request for the main loop
+pub(crate) enum Request {
+    /// Notification action invoked for a message
+    Action(Box<gotify::Message>, config::NotificationAction),
+    /// Notification server appeared, for example after a restart, or is gone
+    ServerChanged(bool),
+    /// Control command, with channel to send its result to
+    Command(control::Command, Reply),
+}
+
+/// Handle to send control commands to the main loop from another thread
+pub(crate) struct CommandSender {
+    /// Event channel
+    tx: mpsc::Sender<Event>,
+    /// Waker to interrupt main loop polling
+    waker: WakerSlot,
+}
+
+impl CommandSender {
+    /// Send command, and get channel to receive its result
+    pub(crate) fn send(
+        &self,
+        command: control::Command,
+    ) -> anyhow::Result<mpsc::Receiver<Result<(), String>>> {
+        let (reply_tx, reply_rx) = mpsc::channel();
+        self.tx
+            .send(Event::Command(command, reply_tx))
+            .map_err(|_| anyhow::anyhow!("Main loop is gone"))?;
+        wake(&self.waker);
+        Ok(reply_rx)
+    }
+}
+
+/// Handle for notification backends to report actions from background threads
+#[derive(Clone)]
+pub(crate) struct ActionSender {
+    /// Event channel
+    tx: mpsc::Sender<Event>,
+    /// Waker to interrupt main loop polling
+    waker: WakerSlot,
+}
+
+#[cfg_attr(
+    all(target_os = "macos", not(test)),
+    expect(dead_code, reason = "Only XDG notification servers support actions")
+)]
+impl ActionSender {
+    /// Report action invoked on a notification, return false if the main loop is gone
+    pub(crate) fn invoked(&self, notif_id: u32, key: &str) -> bool {
+        self.send(Event::Invoked(notif_id, key.to_owned()))
+    }
+
+    /// Report notification closed, return false if the main loop is gone
+    pub(crate) fn closed(&self, notif_id: u32) -> bool {
+        self.send(Event::Closed(notif_id))
+    }
+
+    /// Send event and wake main loop up
+    fn send(&self, event: Event) -> bool {
+        // Receiver is gone if the daemon is exiting, nothing to do
+        let sent = self.tx.send(event).is_ok();
+        if sent {
+            wake(&self.waker);
+        }
+        sent
+    }
+}
+
+/// Main loop waker, replaced on each reconnect
+type WakerSlot = Arc<Mutex<Option<Arc<mio::Waker>>>>;
+
+/// Tracks notifications for invoked actions and receives control commands,
+/// without blocking the main loop
+pub(crate) struct Dispatcher {
+    /// Event channel from background threads
+    tx: mpsc::Sender<Event>,
+    /// Event channel to the main loop
+    rx: mpsc::Receiver<Event>,
+    /// Waker to interrupt main loop polling when an event is sent
+    waker: WakerSlot,
+    /// Messages of watched notifications with their notification id, oldest first
+    watched: VecDeque<(u32, gotify::Message)>,
+}
+
+impl Dispatcher {
+    /// Create dispatcher with no watched notification
+    pub(crate) fn new() -> Self {
+        let (tx, rx) = mpsc::channel();
+        Self {
+            tx,
+            rx,
+            waker: Arc::new(Mutex::new(None)),
+            watched: VecDeque::new(),
+        }
+    }
+
+    /// Get handle to send control commands
+    pub(crate) fn command_sender(&self) -> CommandSender {
+        CommandSender {
+            tx: self.tx.clone(),
+            waker: Arc::clone(&self.waker),
+        }
+    }
+
+    /// Get handle for notification backends to report actions
+    pub(crate) fn action_sender(&self) -> ActionSender {
+        ActionSender {
+            tx: self.tx.clone(),
+            waker: Arc::clone(&self.waker),
+        }
+    }
+
+    /// Set waker of the current main loop poller
+    pub(crate) fn set_waker(&self, waker: Arc<mio::Waker>) {
+        *self.waker.lock().unwrap_or_else(PoisonError::into_inner) = Some(waker);
+    }
+
+    /// Track message of a notification with actions, reported by the notification backend.
+    /// If the notification was updated, its message is replaced.
+    /// The oldest notification is forgotten if too many are tracked
+    pub(crate) fn track(&mut self, notif_id: u32, msg: &gotify::Message) {
+        self.watched.retain(|(id, _)| *id != notif_id);
+        self.watched.push_back((notif_id, msg.clone()));
+        if self.watched.len() > MAX_WATCHED {
+            self.watched.pop_front();
+        }
+    }
+
+    /// Stop tracking notification, and get its message
+    fn untrack(&mut self, notif_id: u32) -> Option<gotify::Message> {
+        let idx = self.watched.iter().position(|(id, _)| *id == notif_id)?;
+        self.watched.remove(idx).map(|(_, msg)| msg)
+    }
+
+    /// Watch the XDG notification server appearing or disappearing on the session bus
+    /// in a background thread
+    #[cfg(all(unix, not(target_os = "macos")))]
+    pub(crate) fn watch_server(&self) -> anyhow::Result<()> {
+        let connection = zbus::blocking::Connection::session()?;
+        let proxy = zbus::blocking::fdo::DBusProxy::new(&connection)?;
+        let signals =
+            proxy.receive_name_owner_changed_with_args(&[(0, backend::SERVER_BUS_NAME)])?;
+        let tx = self.tx.clone();
+        let waker = Arc::clone(&self.waker);
+        thread::Builder::new()
+            .name("notif-server".to_owned())
+            .spawn(move || {
+                for signal in signals {
+                    let available = match signal.args() {
+                        Ok(args) => args.new_owner().is_some(),
+                        Err(err) => {
+                            log::warn!("Invalid name owner change signal: {err}");
+                            continue;
+                        }
+                    };
+                    if tx.send(Event::ServerChanged(available)).is_err() {
+                        break;
+                    }
+                    wake(&waker);
+                }
+            })?;
+        Ok(())
+    }
+
+    /// Get actions invoked and commands received since last call
+    pub(crate) fn requests(&mut self) -> Vec<Request> {
+        let mut requests = Vec::new();
+        let events: Vec<Event> = self.rx.try_iter().collect();
+        for event in events {
+            log::debug!("Notification event: {event:?}");
+            match event {
+                Event::Closed(id) => {
+                    self.untrack(id);
+                }
+                Event::Invoked(id, key) => {
+                    // Other actions, like the default one, leave the notification tracked
+                    let Some(action) = config::NotificationAction::from_key(&key) else {
+                        log::debug!("Ignoring action {key:?}");
+                        continue;
+                    };
+                    if let Some(msg) = self.untrack(id) {
+                        requests.push(Request::Action(Box::new(msg), action));
+                    }
+                }
+                Event::ServerChanged(available) => {
+                    requests.push(Request::ServerChanged(available));
+                }
+                Event::Command(command, reply) => {
+                    requests.push(Request::Command(command, reply));
+                }
+            }
+        }
+        requests
+    }
+}
+
+/// Wake main loop up, if it is polling
+fn wake(waker: &WakerSlot) {
+    if let Some(waker) = waker
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .as_ref()
+        && let Err(err) = waker.wake()
+    {
+        log::warn!("Failed to wake main loop: {err}");
+    }
+}
+
+/// Default command to write text to the clipboard
+fn default_clipboard_command() -> &'static str {
+    if cfg!(target_os = "macos") {
+        "pbcopy"
+    } else if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        "wl-copy"
+    } else {
+        "xclip -selection clipboard"
+    }
+}
+
+/// Copy text to the clipboard in the background, with the given command or a platform default
+pub(crate) fn copy_text(text: &str, command: Option<&str>) -> anyhow::Result<()> {
+    let command = command.unwrap_or_else(|| default_clipboard_command());
+    spawn_command(command, &[], Some(text.to_owned()))
+}
+
+/// Open URL with the default browser in the background
+pub(crate) fn open_url(url: &url::Url) -> anyhow::Result<()> {
+    let command = if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+    spawn_command(command, &[url.as_str()], None)
+}
+
+/// Run command with extra arguments and optional standard input, without waiting for it
+fn spawn_command(command: &str, extra_args: &[&str], stdin: Option<String>) -> anyhow::Result<()> {
+    let args = shlex::split(command)
+        .ok_or_else(|| anyhow::anyhow!("Failed to parse command {command:?}"))?;
+    let (program, args) = args
+        .split_first()
+        .ok_or_else(|| anyhow::anyhow!("Empty command"))?;
+    log::debug!("Running {command:?} {extra_args:?}");
+    let mut child = Command::new(program)
+        .args(args)
+        .args(extra_args)
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .spawn()?;
+    let command = command.to_owned();
+    thread::Builder::new()
+        .name("action-command".to_owned())
+        .spawn(move || {
+            if let (Some(data), Some(mut child_stdin)) = (stdin, child.stdin.take())
+                && let Err(err) = child_stdin.write_all(data.as_bytes())
+            {
+                log::warn!("Failed to write to {command:?}: {err}");
+            }
+            match child.wait() {
+                Ok(status) if !status.success() => {
+                    log::warn!("Command {command:?} failed with status {status:?}");
+                }
+                Ok(_) => {}
+                Err(err) => log::warn!("Failed to wait for command {command:?}: {err}"),
+            }
+        })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dispatch_events() {
+        let mut dispatcher = Dispatcher::new();
+        let msg = gotify::test_message(12, 1, 5);
+        dispatcher.track(1, &msg);
+        dispatcher.track(2, &msg);
+        for event in [
+            Event::Invoked(1, "default".to_owned()),
+            Event::Invoked(1, "delete".to_owned()),
+            Event::Invoked(1, "delete".to_owned()),
+            Event::Closed(2),
+            Event::Invoked(2, "copy".to_owned()),
+        ] {
+            dispatcher.tx.send(event).unwrap();
+        }
+        let (reply_tx, _reply_rx) = mpsc::channel();
+        let command = control::Command::Snooze {
+            msg_id: 12,
+            duration: None,
+        };
+        dispatcher
+            .tx
+            .send(Event::Command(command, reply_tx))
+            .unwrap();
+        let requests = dispatcher.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(matches!(
+            &requests[0],
+            Request::Action(m, config::NotificationAction::Delete) if m.id == 12
+        ));
+        assert!(matches!(
+            &requests[1],
+            Request::Command(control::Command::Snooze { msg_id: 12, .. }, _)
+        ));
+        assert!(dispatcher.watched.is_empty());
+
+        // Oldest notifications are forgotten
+        for notif_id in 0..=u32::try_from(MAX_WATCHED).unwrap() {
+            dispatcher.track(notif_id, &msg);
+        }
+        assert_eq!(dispatcher.watched.len(), MAX_WATCHED);
+        assert!(dispatcher.untrack(0).is_none());
+        assert!(dispatcher.untrack(1).is_some());
+    }
+}
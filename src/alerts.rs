@@ -0,0 +1,191 @@
+//! Alerts: notifications of important messages, repeated until acknowledged.
+//! Pending alerts are only kept in memory, so they are lost on restart
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use crate::{config, gotify};
+
+/// Default delay between reminders
+const DEFAULT_REPEAT: Duration = Duration::from_secs(5 * 60);
+
+/// Default delay before escalation
+const DEFAULT_ESCALATION: Duration = Duration::from_secs(30 * 60);
+
+/// Alert waiting to be acknowledged
+struct Alert {
+    /// The message
+    message: gotify::Message,
+    /// Id of the notification, if the notification server supports updating it
+    notif_id: Option<u32>,
+    /// Number of reminders so far
+    reminders: usize,
+    /// When to remind next, never if too far in the future
+    next_reminder: Option<Instant>,
+    /// When to escalate, if not done yet
+    escalate_at: Option<Instant>,
+}
+
+/// Reminder for an alert not acknowledged yet
+pub(crate) struct Reminder {
+    /// The message
+    pub message: gotify::Message,
+    /// Id of the notification to update
+    pub notif_id: Option<u32>,
+    /// Reminder number, starting at 1
+    pub count: usize,
+}
+
+/// Alerts not acknowledged yet
+pub(crate) struct Alerts {
+    /// Minimum priority of alert messages, disabled if unset
+    min_priority: Option<i64>,
+    /// Delay between reminders
+    repeat: Duration,
+    /// Delay before escalation, if enabled
+    escalation: Option<Duration>,
+    /// Pending alerts by message id
+    pending: HashMap<i64, Alert>,
+}
+
+impl Alerts {
+    /// Create alert tracker from configuration
+    pub(crate) fn new(cfg: &config::AlertConfig) -> Self {
+        Self {
+            min_priority: cfg.min_priority,
+            repeat: cfg.repeat_minutes.map_or(DEFAULT_REPEAT, |m| {
+                Duration::from_secs(m.saturating_mul(60))
+            }),
+            escalation: cfg.escalation_command.as_ref().map(|_| {
+                cfg.escalation_minutes.map_or(DEFAULT_ESCALATION, |m| {
+                    Duration::from_secs(m.saturating_mul(60))
+                })
+            }),
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Should this message be shown as an alert?
+    pub(crate) fn is_alert(&self, msg: &gotify::Message) -> bool {
+        self.min_priority.is_some_and(|p| msg.priority >= p)
+    }
+
+    /// Is this message an alert waiting to be acknowledged?
+    pub(crate) fn is_active(&self, msg_id: i64) -> bool {
+        self.pending.contains_key(&msg_id)
+    }
+
+    /// Start tracking alert
+    pub(crate) fn start(&mut self, msg: &gotify::Message, now: Instant) {
+        log::info!("Message {} is an alert", msg.id);
+        self.pending.insert(
+            msg.id,
+            Alert {
+                message: msg.clone(),
+                notif_id: None,
+                reminders: 0,
+                next_reminder: now.checked_add(self.repeat),
+                escalate_at: self.escalation.and_then(|d| now.checked_add(d)),
+            },
+        );
+    }
+
+    /// Record id of the notification shown for an alert
+    pub(crate) fn set_notif_id(&mut self, msg_id: i64, notif_id: u32) {
+        if let Some(alert) = self.pending.get_mut(&msg_id) {
+            alert.notif_id = Some(notif_id);
+        }
+    }
+
+    /// Stop tracking alert, return true if it was active
+    pub(crate) fn acknowledge(&mut self, msg_id: i64) -> bool {
+        self.pending.remove(&msg_id).is_some()
+    }
+
+    /// Get reminders that are due, and schedule the next ones
+    pub(crate) fn due_reminders(&mut self, now: Instant) -> Vec<Reminder> {
+        self.pending
+            .values_mut()
+            .filter(|a| a.next_reminder.is_some_and(|t| t <= now))
+            .map(|a| {
+                a.reminders += 1;
+                a.next_reminder = now.checked_add(self.repeat);
+                Reminder {
+                    message: a.message.clone(),
+                    notif_id: a.notif_id,
+                    count: a.reminders,
+                }
+            })
+            .collect()
+    }
+
+    /// Get messages of alerts to escalate, each alert is only escalated once
+    pub(crate) fn due_escalations(&mut self, now: Instant) -> Vec<gotify::Message> {
+        self.pending
+            .values_mut()
+            .filter(|a| a.escalate_at.is_some_and(|t| t <= now))
+            .map(|a| {
+                a.escalate_at = None;
+                a.message.clone()
+            })
+            .collect()
+    }
+
+    /// Time of the next reminder or escalation, if any
+    pub(crate) fn next_due(&self) -> Option<Instant> {
+        self.pending
+            .values()
+            .flat_map(|a| [a.next_reminder, a.escalate_at])
+            .flatten()
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remind_and_escalate() {
+        let mut alerts = Alerts::new(&config::AlertConfig {
+            min_priority: Some(8),
+            repeat_minutes: Some(1),
+            escalation_command: Some("page-oncall".to_owned()),
+            escalation_minutes: Some(3),
+        });
+        assert!(!alerts.is_alert(&gotify::test_message(1, 1, 7)));
+        assert!(alerts.is_alert(&gotify::test_message(1, 1, 8)));
+
+        let now = Instant::now();
+        alerts.start(&gotify::test_message(1, 1, 8), now);
+        alerts.set_notif_id(1, 42);
+        assert!(alerts.is_active(1));
+        assert_eq!(alerts.next_due(), Some(now + Duration::from_secs(60)));
+        assert!(alerts.due_reminders(now).is_empty());
+
+        let later = now + Duration::from_secs(60);
+        let reminders = alerts.due_reminders(later);
+        assert_eq!(reminders.len(), 1);
+        assert_eq!(reminders[0].message.id, 1);
+        assert_eq!(reminders[0].notif_id, Some(42));
+        assert_eq!(reminders[0].count, 1);
+        assert!(alerts.due_escalations(later).is_empty());
+
+        let much_later = now + Duration::from_secs(3 * 60);
+        assert_eq!(alerts.due_reminders(much_later)[0].count, 2);
+        assert_eq!(alerts.due_escalations(much_later).len(), 1);
+        assert!(alerts.due_escalations(much_later).is_empty());
+
+        assert!(alerts.acknowledge(1));
+        assert!(!alerts.acknowledge(1));
+        assert!(alerts.next_due().is_none());
+    }
+
+    #[test]
+    fn disabled() {
+        let alerts = Alerts::new(&config::AlertConfig::default());
+        assert!(!alerts.is_alert(&gotify::test_message(1, 1, 10)));
+    }
+}
@@ -0,0 +1,451 @@
+//! Gotify application metadata registry, persisted in cache
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::config;
+
+/// Delay after which the application list is fetched again from the server
+const TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Minimum delay between two fetches triggered by an unknown application id
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Delay before retrying after a first failure, doubled for each subsequent one
+const FAILURE_MIN_DELAY: Duration = Duration::from_secs(60);
+
+/// Maximum delay before retrying after failures
+const FAILURE_MAX_DELAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Extensions of cached icon files, those of images Gotify accepts for apps
+const ICON_EXTENSIONS: [&str; 4] = ["png", "jpg", "jpeg", "gif"];
+
+/// Negative cache entry: consecutive failures to get something, and when to try again
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(test, derive(Eq, PartialEq))]
+pub(crate) struct Failure {
+    /// Number of consecutive failures
+    count: u32,
+    /// Do not try again before this time
+    retry_at: SystemTime,
+}
+
+impl Failure {
+    /// Record a new failure after a previous one, if any
+    fn next(previous: Option<&Self>) -> Self {
+        let count = previous.map_or(1, |f| f.count.saturating_add(1));
+        let delay = FAILURE_MIN_DELAY
+            .saturating_mul(2_u32.saturating_pow(count - 1))
+            .min(FAILURE_MAX_DELAY);
+        Self {
+            count,
+            retry_at: SystemTime::now() + delay,
+        }
+    }
+
+    /// Can we try again?
+    fn can_retry(failure: Option<&Self>) -> bool {
+        failure.is_none_or(|f| SystemTime::now() >= f.retry_at)
+    }
+}
+
+/// Gotify application metadata
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(test, derive(Eq, PartialEq))]
+pub(crate) struct App {
+    /// App id
+    pub id: i64,
+    /// App name
+    pub name: String,
+    /// App description
+    pub description: String,
+    /// Image URL relative to the server base URL, if any
+    pub image_url: Option<String>,
+    /// Local cached icon filepath, if downloaded
+    pub icon_filepath: Option<PathBuf>,
+    /// Last icon download failure, if any
+    #[serde(default)]
+    pub icon_failure: Option<Failure>,
+}
+
+impl App {
+    /// Can we try to download icon?
+    pub(crate) fn can_download_icon(&self) -> bool {
+        Failure::can_retry(self.icon_failure.as_ref())
+    }
+}
+
+/// Serialized registry
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+struct CacheData {
+    /// Time of last fetch from server
+    fetched_at: Option<SystemTime>,
+    /// Last fetch failure, if any
+    #[serde(default)]
+    fetch_failure: Option<Failure>,
+    /// Known applications
+    apps: Vec<App>,
+}
+
+/// Registry of Gotify applications
+pub(crate) struct Registry {
+    /// Applications by id
+    apps: HashMap<i64, App>,
+    /// Time of last fetch from server
+    fetched_at: Option<SystemTime>,
+    /// Last fetch failure, if any
+    fetch_failure: Option<Failure>,
+    /// Cache filepath
+    filepath: PathBuf,
+    /// Has registry changed since last load or save?
+    dirty: bool,
+    /// Local per app configuration
+    overrides: Vec<config::AppConfig>,
+}
+
+impl Registry {
+    /// Load registry from cache file, or start with an empty one
+    pub(crate) fn load(filepath: PathBuf, overrides: Vec<config::AppConfig>) -> Self {
+        let data = match Self::read(&filepath) {
+            Ok(data) => data,
+            Err(err) => {
+                if filepath.exists() {
+                    log::warn!("Failed to load application cache {filepath:?}: {err:?}");
+                }
+                CacheData::default()
+            }
+        };
+        log::debug!(
+            "Loaded {} application(s) from {filepath:?}",
+            data.apps.len()
+        );
+        Self {
+            apps: data.apps.into_iter().map(|a| (a.id, a)).collect(),
+            fetched_at: data.fetched_at,
+            fetch_failure: data.fetch_failure,
+            filepath,
+            dirty: false,
+            overrides,
+        }
+    }
+
+    /// Read and parse cache file
+    fn read(filepath: &Path) -> anyhow::Result<CacheData> {
+        let json_data = fs::read_to_string(filepath)?;
+        Ok(serde_json::from_str(&json_data)?)
+    }
+
+    /// Write registry to cache file if it has changed
+    pub(crate) fn save(&mut self) -> anyhow::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let mut apps: Vec<_> = self.apps.values().cloned().collect();
+        apps.sort_unstable_by_key(|a| a.id);
+        let data = CacheData {
+            fetched_at: self.fetched_at,
+            fetch_failure: self.fetch_failure.clone(),
+            apps,
+        };
+        // Write to a temporary file first, so that the cache is never left half written
+        let mut tmp_filepath = self.filepath.clone().into_os_string();
+        tmp_filepath.push(".tmp");
+        fs::write(&tmp_filepath, serde_json::to_vec(&data)?)?;
+        fs::rename(&tmp_filepath, &self.filepath)?;
+        log::debug!("{:?} written", self.filepath);
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Get application by id
+    pub(crate) fn get(&self, id: i64) -> Option<&App> {
+        self.apps.get(&id)
+    }
+
+    /// Get local configuration for an app, matching either by id, or by name if app is known
+    pub(crate) fn config(&self, id: i64) -> Option<&config::AppConfig> {
+        let name = self.apps.get(&id).map(|a| a.name.as_str());
+        self.overrides.iter().find(|o| o.matches(id, name))
+    }
+
+    /// Iterate over all applications
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &App> {
+        self.apps.values()
+    }
+
+    /// Set local icon filepath for an application
+    pub(crate) fn set_icon_filepath(&mut self, id: i64, icon_filepath: Option<PathBuf>) {
+        if let Some(app) = self.apps.get_mut(&id)
+            && app.icon_filepath != icon_filepath
+        {
+            app.icon_filepath = icon_filepath;
+            self.dirty = true;
+        }
+    }
+
+    /// Record result of an icon download
+    pub(crate) fn set_icon_result(&mut self, id: i64, success: bool) {
+        if let Some(app) = self.apps.get_mut(&id)
+            && !(success && app.icon_failure.is_none())
+        {
+            app.icon_failure = if success {
+                None
+            } else {
+                let failure = Failure::next(app.icon_failure.as_ref());
+                log::debug!("App {id} icon {failure:?}");
+                Some(failure)
+            };
+            self.dirty = true;
+        }
+    }
+
+    /// Record application list fetch failure
+    pub(crate) fn set_fetch_failed(&mut self) {
+        self.fetch_failure = Some(Failure::next(self.fetch_failure.as_ref()));
+        self.dirty = true;
+    }
+
+    /// Is application list too old, and needs to be fetched again?
+    pub(crate) fn is_stale(&self) -> bool {
+        self.fetched_at
+            .and_then(|t| t.elapsed().ok())
+            .is_none_or(|e| e >= TTL)
+    }
+
+    /// Can we fetch application list again, because we got a message from an unknown application?
+    pub(crate) fn can_refresh(&self) -> bool {
+        Failure::can_retry(self.fetch_failure.as_ref())
+            && self
+                .fetched_at
+                .and_then(|t| t.elapsed().ok())
+                .is_none_or(|e| e >= MIN_REFRESH_INTERVAL)
+    }
+
+    /// Replace application list with a fresh one from the server, keeping local icons
+    /// unless the image has changed
+    pub(crate) fn update(&mut self, apps: Vec<App>) {
+        let mut old_apps = std::mem::take(&mut self.apps);
+        self.apps = apps
+            .into_iter()
+            .map(|mut app| {
+                if let Some(old_app) = old_apps.remove(&app.id) {
+                    if old_app.image_url == app.image_url {
+                        app.icon_filepath = old_app.icon_filepath;
+                        app.icon_failure = old_app.icon_failure;
+                    } else {
+                        log::info!(
+                            "Image of app {} has changed from {:?} to {:?}",
+                            app.id,
+                            old_app.image_url,
+                            app.image_url
+                        );
+                    }
+                }
+                (app.id, app)
+            })
+            .collect();
+        self.fetched_at = Some(SystemTime::now());
+        self.fetch_failure = None;
+        self.dirty = true;
+    }
+
+    /// Cache directory
+    fn dir(&self) -> anyhow::Result<&Path> {
+        self.filepath
+            .parent()
+            .ok_or_else(|| anyhow::anyhow!("Invalid cache filepath {:?}", self.filepath))
+    }
+
+    /// Remove cached icon files that do not belong to any application,
+    /// return how many were removed.
+    /// Icon paths must have been assigned first, or the icons would be removed
+    pub(crate) fn prune(&self) -> anyhow::Result<usize> {
+        let used: HashSet<&Path> = self
+            .apps
+            .values()
+            .filter_map(|a| a.icon_filepath.as_deref())
+            .collect();
+        let mut count = 0;
+        for entry in fs::read_dir(self.dir()?)? {
+            let path = entry?.path();
+            // Files being downloaded have another extension
+            if used.contains(path.as_path())
+                || !path
+                    .extension()
+                    .is_some_and(|e| ICON_EXTENSIONS.iter().any(|i| e.eq_ignore_ascii_case(i)))
+                || !path.is_file()
+            {
+                continue;
+            }
+            log::info!("Removing orphan cache file {path:?}");
+            fs::remove_file(&path)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Remove all cached files and forget all applications, return how many files were removed
+    pub(crate) fn clear(&mut self) -> anyhow::Result<usize> {
+        self.apps.clear();
+        self.fetched_at = None;
+        self.fetch_failure = None;
+        self.dirty = false;
+        let mut count = 0;
+        for entry in fs::read_dir(self.dir()?)? {
+            let path = entry?.path();
+            // Skip files being downloaded
+            if path.is_file() && path.extension().is_none_or(|e| e != "tmp") {
+                log::debug!("Removing cache file {path:?}");
+                fs::remove_file(&path)?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    fn app(id: i64, image_url: Option<&str>) -> App {
+        App {
+            id,
+            name: format!("app{id}"),
+            description: String::new(),
+            image_url: image_url.map(ToOwned::to_owned),
+            icon_filepath: None,
+            icon_failure: None,
+        }
+    }
+
+    #[test]
+    fn update_keeps_icons() {
+        let mut registry = Registry::load(PathBuf::from("/nonexistent/apps.json"), vec![]);
+        assert!(registry.is_stale());
+        assert!(registry.can_refresh());
+
+        registry.update(vec![app(1, Some("image/a.png")), app(2, None)]);
+        assert!(!registry.is_stale());
+        assert!(!registry.can_refresh());
+        registry.set_icon_filepath(1, Some(PathBuf::from("/tmp/a.png")));
+
+        registry.update(vec![app(1, Some("image/a.png")), app(3, None)]);
+        assert_eq!(
+            registry.get(1).unwrap().icon_filepath,
+            Some(PathBuf::from("/tmp/a.png"))
+        );
+        assert!(registry.get(2).is_none());
+        assert_eq!(registry.get(3), Some(&app(3, None)));
+    }
+
+    #[test]
+    fn app_config() {
+        let overrides: Vec<config::AppConfig> = ["id = 2", r#"name = "app3""#]
+            .into_iter()
+            .map(|c| toml::from_str(c).unwrap())
+            .collect();
+        let mut registry = Registry::load(PathBuf::from("/nonexistent/apps.json"), overrides);
+        registry.update(vec![app(1, None), app(3, None)]);
+        assert!(registry.config(1).is_none());
+        assert_eq!(registry.config(2).unwrap().id, Some(2));
+        assert_eq!(registry.config(3).unwrap().name.as_deref(), Some("app3"));
+    }
+
+    #[test]
+    fn image_change() {
+        let mut registry = Registry::load(PathBuf::from("/nonexistent/apps.json"), vec![]);
+        registry.update(vec![
+            app(1, Some("image/a.png")),
+            app(2, Some("image/b.png")),
+        ]);
+        registry.set_icon_filepath(1, Some(PathBuf::from("/tmp/a.png")));
+        registry.set_icon_filepath(2, Some(PathBuf::from("/tmp/b.png")));
+        registry.set_icon_result(2, false);
+
+        registry.update(vec![
+            app(1, Some("image/a.png")),
+            app(2, Some("image/c.png")),
+        ]);
+        assert_eq!(
+            registry.get(1).unwrap().icon_filepath,
+            Some(PathBuf::from("/tmp/a.png"))
+        );
+        assert_eq!(registry.get(2), Some(&app(2, Some("image/c.png"))));
+    }
+
+    #[test]
+    fn prune_clear() {
+        let dir = env::temp_dir().join(format!(
+            "{}-prune-{}",
+            env!("CARGO_PKG_NAME"),
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        let mut registry = Registry::load(dir.join("apps.json"), vec![]);
+        registry.update(vec![app(1, Some("image/a.png"))]);
+        registry.set_icon_filepath(1, Some(dir.join("a.png")));
+        registry.save().unwrap();
+        for filename in ["a.png", "b.png", "c.png.tmp", "d.txt"] {
+            fs::write(dir.join(filename), "").unwrap();
+        }
+
+        assert_eq!(registry.prune().unwrap(), 1);
+        assert!(dir.join("a.png").is_file());
+        assert!(!dir.join("b.png").exists());
+        assert!(dir.join("apps.json").is_file());
+        assert!(dir.join("d.txt").is_file());
+
+        assert_eq!(registry.clear().unwrap(), 3);
+        assert!(registry.get(1).is_none());
+        assert!(dir.join("c.png.tmp").is_file());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_file(dir.join("c.png.tmp")).unwrap();
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn negative_cache() {
+        let mut registry = Registry::load(PathBuf::from("/nonexistent/apps.json"), vec![]);
+        registry.set_fetch_failed();
+        assert!(!registry.can_refresh());
+
+        registry.update(vec![app(1, Some("image/a.png"))]);
+        assert!(registry.get(1).unwrap().can_download_icon());
+        registry.set_icon_result(1, false);
+        assert!(!registry.get(1).unwrap().can_download_icon());
+        registry.set_icon_result(1, false);
+        let failure = registry.get(1).unwrap().icon_failure.clone().unwrap();
+        assert_eq!(failure.count, 2);
+        assert!(failure.retry_at > SystemTime::now() + FAILURE_MIN_DELAY);
+
+        registry.update(vec![app(1, Some("image/a.png"))]);
+        assert!(!registry.get(1).unwrap().can_download_icon());
+        registry.set_icon_result(1, true);
+        assert!(registry.get(1).unwrap().can_download_icon());
+    }
+
+    #[test]
+    fn save_load() {
+        let filepath = env::temp_dir().join(format!(
+            "{}-apps-{}.json",
+            env!("CARGO_PKG_NAME"),
+            std::process::id()
+        ));
+        let mut registry = Registry::load(filepath.clone(), vec![]);
+        registry.update(vec![app(1, Some("image/a.png")), app(2, None)]);
+        registry.save().unwrap();
+
+        let loaded = Registry::load(filepath.clone(), vec![]);
+        fs::remove_file(&filepath).unwrap();
+        assert_eq!(loaded.get(1), registry.get(1));
+        assert_eq!(loaded.get(2), registry.get(2));
+        assert!(!loaded.is_stale());
+    }
+}
@@ -0,0 +1,749 @@
+//! Notification backends
+
+#[cfg(all(unix, not(target_os = "macos")))]
+use std::{collections::HashMap, fs};
+use std::{
+    io::{self, Write as _},
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+};
+
+use crate::{actions, config, gotify};
+
+/// Name of the XDG Desktop entry, without the .desktop suffix
+const DESKTOP_ENTRY_NAME: &str = env!("CARGO_PKG_NAME");
+
+/// D-Bus name of the XDG notification server
+#[cfg(all(unix, not(target_os = "macos")))]
+pub(crate) const SERVER_BUS_NAME: &str = "org.freedesktop.Notifications";
+
+/// D-Bus object path of the XDG notification server
+#[cfg(all(unix, not(target_os = "macos")))]
+const SERVER_PATH: &str = "/org/freedesktop/Notifications";
+
+/// D-Bus name of the XDG desktop portal
+#[cfg(all(unix, not(target_os = "macos")))]
+const PORTAL_BUS_NAME: &str = "org.freedesktop.portal.Desktop";
+
+/// D-Bus object path of the XDG desktop portal
+#[cfg(all(unix, not(target_os = "macos")))]
+const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+
+/// D-Bus interface of the XDG desktop portal notifications
+#[cfg(all(unix, not(target_os = "macos")))]
+const PORTAL_INTERFACE: &str = "org.freedesktop.portal.Notification";
+
+/// Is a notification server running, or can it be started by D-Bus activation?
+/// Assume it is if the session bus can not be queried
+pub(crate) fn server_available() -> bool {
+    check_server(true)
+}
+
+/// Is a notification server running? Assume it is if the session bus can not be queried
+pub(crate) fn server_running() -> bool {
+    check_server(false)
+}
+
+/// Check notification server presence, optionally counting one that D-Bus can activate
+fn check_server(
+    #[cfg_attr(
+        not(all(unix, not(target_os = "macos"))),
+        expect(
+            unused_variables,
+            reason = "Only XDG notification servers can be checked"
+        )
+    )]
+    activatable: bool,
+) -> bool {
+    #[cfg(all(unix, not(target_os = "macos")))]
+    {
+        query_server(activatable).unwrap_or_else(|err| {
+            log::warn!("Failed to check notification server availability: {err:?}");
+            true
+        })
+    }
+    #[cfg(not(all(unix, not(target_os = "macos"))))]
+    true
+}
+
+/// Query session bus for the notification server name owner, or activation
+#[cfg(all(unix, not(target_os = "macos")))]
+fn query_server(activatable: bool) -> anyhow::Result<bool> {
+    let connection = zbus::blocking::Connection::session()?;
+    let proxy = zbus::blocking::fdo::DBusProxy::new(&connection)?;
+    Ok(proxy.name_has_owner(SERVER_BUS_NAME.try_into()?)?
+        || (activatable
+            && proxy
+                .list_activatable_names()?
+                .iter()
+                .any(|n| n.as_str() == SERVER_BUS_NAME)))
+}
+
+/// Notification server features we adapt to
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[expect(clippy::struct_excessive_bools)] // Capabilities are independent
+pub(crate) struct Capabilities {
+    /// Can show action buttons
+    pub actions: bool,
+    /// Interprets body markup
+    pub body_markup: bool,
+    /// Shows images from body markup
+    pub body_images: bool,
+    /// Can show raw image data
+    pub images: bool,
+    /// Can play sounds from hints
+    pub sound: bool,
+}
+
+impl Default for Capabilities {
+    /// Capabilities assumed when they can not be queried
+    fn default() -> Self {
+        let xdg = cfg!(all(unix, not(target_os = "macos")));
+        Self {
+            actions: xdg,
+            body_markup: xdg,
+            body_images: false,
+            images: xdg,
+            sound: true,
+        }
+    }
+}
+
+impl Capabilities {
+    /// No optional feature
+    const NONE: Self = Self {
+        actions: false,
+        body_markup: false,
+        body_images: false,
+        images: false,
+        sound: false,
+    };
+
+    /// Query and log notification server information & capabilities
+    fn query() -> Self {
+        #[cfg(all(unix, not(target_os = "macos")))]
+        {
+            match notify_rust::get_server_information() {
+                Ok(info) => log::info!(
+                    "Notification server: {} {} by {}, spec version {}",
+                    info.name,
+                    info.version,
+                    info.vendor,
+                    info.spec_version
+                ),
+                Err(err) => log::warn!("Failed to get notification server information: {err}"),
+            }
+            match notify_rust::get_capabilities() {
+                Ok(names) => {
+                    log::info!("Notification server capabilities: {names:?}");
+                    Self::from_names(&names)
+                }
+                Err(err) => {
+                    log::warn!("Failed to get notification server capabilities: {err}");
+                    Self::default()
+                }
+            }
+        }
+        #[cfg(not(all(unix, not(target_os = "macos"))))]
+        Self::default()
+    }
+
+    /// Parse capabilities returned by the XDG notification server
+    #[cfg_attr(
+        all(target_os = "macos", not(test)),
+        expect(dead_code, reason = "Only XDG notification servers have capabilities")
+    )]
+    fn from_names(names: &[String]) -> Self {
+        let has = |name: &str| names.iter().any(|n| n == name);
+        Self {
+            actions: has("actions"),
+            body_markup: has("body-markup"),
+            body_images: has("body-images"),
+            images: has("icon-static") || has("icon-multi"),
+            sound: has("sound"),
+        }
+    }
+}
+
+/// Notification action button
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct Button {
+    /// Action to run
+    pub action: config::NotificationAction,
+    /// Button label
+    pub label: String,
+}
+
+/// Notification to show, independent of the backend
+#[derive(Clone, Debug, Default, serde::Serialize)]
+pub(crate) struct Notification {
+    /// Id of the notification to replace, if any
+    #[serde(skip)]
+    pub replaces_id: Option<u32>,
+    /// Name of the app sending it
+    pub app_name: String,
+    /// Summary, as plain text
+    pub summary: String,
+    /// Body, with markup escaped unless it is trusted
+    pub body: String,
+    /// Icon, the daemon one if unset
+    pub icon: Option<gotify::Icon>,
+    /// Urgency level
+    pub urgency: Option<config::Urgency>,
+    /// Category hint
+    pub category: Option<String>,
+    /// Expiration timeout in milliseconds, 0 to never expire
+    pub timeout: Option<u32>,
+    /// Sound to play
+    pub sound: Option<config::Sound>,
+    /// Action buttons, empty if the backend does not support them
+    pub buttons: Vec<Button>,
+    /// Keep notification until it is dismissed
+    pub resident: bool,
+}
+
+/// Notification backend
+pub(crate) trait Notifier {
+    /// Features supported by the backend
+    fn capabilities(&self) -> Capabilities;
+
+    /// Query capabilities again, after a notification server restart
+    fn refresh_capabilities(&mut self) {}
+
+    /// Can notifications be shown now? They are queued until they can
+    fn available(&self) -> bool {
+        true
+    }
+
+    /// Record whether the notification server is available
+    fn set_available(&mut self, _available: bool) {}
+
+    /// Show notification, return its id if it can be updated later.
+    /// Actions invoked on it are reported to the main loop
+    fn show(&mut self, notif: &Notification) -> anyhow::Result<Option<u32>>;
+
+    /// Close notification shown earlier, if the backend supports it
+    fn close(&mut self, _notif_id: u32) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Create backend selected in configuration
+pub(crate) fn new(
+    cfg: &config::NotificationConfig,
+    sender: actions::ActionSender,
+) -> anyhow::Result<Box<dyn Notifier>> {
+    log::debug!("Using {:?} notification backend", cfg.backend);
+    Ok(match cfg.backend {
+        config::Backend::Freedesktop => Box::new(Freedesktop::new(cfg, sender)),
+        #[cfg(all(unix, not(target_os = "macos")))]
+        config::Backend::Portal => Box::new(Portal::new(cfg)?),
+        #[cfg(not(all(unix, not(target_os = "macos"))))]
+        config::Backend::Portal => anyhow::bail!("XDG desktop portal is only available on Linux"),
+        config::Backend::Stdout => Box::new(Stdout),
+        config::Backend::Noop => Box::new(Noop),
+    })
+}
+
+/// Desktop notifications with notify-rust: XDG notification server over D-Bus,
+/// or native notifications on macOS
+struct Freedesktop {
+    /// Notification server capabilities
+    caps: Capabilities,
+    /// Is the notification server available?
+    available: bool,
+    /// Send icon image files as raw pixel data
+    image_data: bool,
+    /// Command to play sound files with, if the notification server can not play them
+    sound_command: Option<String>,
+    /// Handle to report invoked actions
+    #[cfg_attr(
+        all(target_os = "macos", not(test)),
+        expect(dead_code, reason = "Only XDG notification servers support actions")
+    )]
+    sender: actions::ActionSender,
+    /// Session bus connection, whose notification server signals are listened to
+    #[cfg(all(unix, not(target_os = "macos")))]
+    connection: Option<zbus::blocking::Connection>,
+}
+
+impl Freedesktop {
+    /// Create backend, and query notification server capabilities
+    fn new(cfg: &config::NotificationConfig, sender: actions::ActionSender) -> Self {
+        Self {
+            caps: Capabilities::query(),
+            available: true,
+            image_data: cfg.image_data,
+            sound_command: cfg.sound_command.clone(),
+            sender,
+            #[cfg(all(unix, not(target_os = "macos")))]
+            connection: None,
+        }
+    }
+
+    /// Get session bus connection, listening to notification server signals on first use
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn connection(&mut self) -> anyhow::Result<&zbus::blocking::Connection> {
+        if self.connection.is_none() {
+            let connection = zbus::blocking::Connection::session()?;
+            self.listen(&connection)?;
+            self.connection = Some(connection);
+        }
+        self.connection
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("No session bus connection"))
+    }
+
+    /// Report action & close signals of the notification server from a background thread,
+    /// those of untracked notifications are ignored by the main loop
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn listen(&self, connection: &zbus::blocking::Connection) -> anyhow::Result<()> {
+        let rule = zbus::MatchRule::builder()
+            .msg_type(zbus::message::Type::Signal)
+            .interface(SERVER_BUS_NAME)?
+            .path(SERVER_PATH)?
+            .build();
+        let signals = zbus::blocking::MessageIterator::for_match_rule(rule, connection, None)?;
+        let sender = self.sender.clone();
+        thread::Builder::new()
+            .name("notif-signals".to_owned())
+            .spawn(move || {
+                for signal in signals {
+                    let signal = match signal {
+                        Ok(signal) => signal,
+                        Err(err) => {
+                            log::warn!("Failed to receive notification signal: {err}");
+                            continue;
+                        }
+                    };
+                    let header = signal.header();
+                    let body = signal.body();
+                    let sent = match header.member().map(zbus::names::MemberName::as_str) {
+                        Some("ActionInvoked") => match body.deserialize::<(u32, String)>() {
+                            Ok((notif_id, key)) => sender.invoked(notif_id, &key),
+                            Err(err) => {
+                                log::warn!("Invalid action signal: {err}");
+                                true
+                            }
+                        },
+                        Some("NotificationClosed") => match body.deserialize::<(u32, u32)>() {
+                            Ok((notif_id, _reason)) => sender.closed(notif_id),
+                            Err(err) => {
+                                log::warn!("Invalid close signal: {err}");
+                                true
+                            }
+                        },
+                        _ => true,
+                    };
+                    if !sent {
+                        break;
+                    }
+                }
+            })?;
+        Ok(())
+    }
+}
+
+impl Notifier for Freedesktop {
+    fn capabilities(&self) -> Capabilities {
+        self.caps
+    }
+
+    fn refresh_capabilities(&mut self) {
+        self.caps = Capabilities::query();
+    }
+
+    fn available(&self) -> bool {
+        self.available
+    }
+
+    fn set_available(&mut self, available: bool) {
+        self.available = available;
+    }
+
+    fn show(&mut self, notif: &Notification) -> anyhow::Result<Option<u32>> {
+        let mut desktop_notif = notify_rust::Notification::new();
+        desktop_notif.summary(&notif.summary).body(&notif.body);
+        #[cfg(all(unix, not(target_os = "macos")))]
+        {
+            if let Some(notif_id) = notif.replaces_id {
+                desktop_notif.id(notif_id);
+            }
+            desktop_notif
+                .appname(&notif.app_name)
+                .hint(notify_rust::Hint::DesktopEntry(
+                    DESKTOP_ENTRY_NAME.to_owned(),
+                ));
+            if let Some(urgency) = notif.urgency {
+                desktop_notif.urgency(match urgency {
+                    config::Urgency::Low => notify_rust::Urgency::Low,
+                    config::Urgency::Normal => notify_rust::Urgency::Normal,
+                    config::Urgency::Critical => notify_rust::Urgency::Critical,
+                });
+            }
+            if let Some(category) = &notif.category {
+                desktop_notif.hint(notify_rust::Hint::Category(category.to_owned()));
+            }
+            for button in &notif.buttons {
+                desktop_notif.action(button.action.key(), &button.label);
+            }
+            if notif.resident {
+                desktop_notif.hint(notify_rust::Hint::Resident(true));
+            }
+        }
+        if let Some(timeout) = notif.timeout {
+            desktop_notif.timeout(if timeout == 0 {
+                notify_rust::Timeout::Never
+            } else {
+                notify_rust::Timeout::Milliseconds(timeout)
+            });
+        }
+        // Sounds are played locally only if the notification server can not play them
+        let local_sound = notif.sound.as_ref().filter(|s| !server_plays(s, self.caps));
+        if let Some(sound) = &notif.sound {
+            apply_sound(&mut desktop_notif, sound, local_sound.is_none());
+        }
+        apply_icon(
+            &mut desktop_notif,
+            notif.icon.as_ref(),
+            self.image_data && self.caps.images,
+        )?;
+
+        let handle = desktop_notif.show()?;
+
+        if let Some(local_sound) = local_sound {
+            play_local_sound(local_sound, self.sound_command.as_deref());
+        }
+
+        // Only XDG notification servers allow updating a notification
+        #[cfg(all(unix, not(target_os = "macos")))]
+        {
+            let notif_id = handle.id();
+            if !notif.buttons.is_empty()
+                && let Err(err) = self.connection()
+            {
+                log::warn!("Failed to watch notification actions: {err:?}");
+            }
+            Ok(Some(notif_id))
+        }
+        #[cfg(not(all(unix, not(target_os = "macos"))))]
+        {
+            let _handle = handle;
+            Ok(None)
+        }
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn close(&mut self, notif_id: u32) -> anyhow::Result<()> {
+        self.connection()?.call_method(
+            Some(SERVER_BUS_NAME),
+            SERVER_PATH,
+            Some(SERVER_BUS_NAME),
+            "CloseNotification",
+            &(notif_id,),
+        )?;
+        Ok(())
+    }
+}
+
+/// Set notification icon, and send image files as raw data if enabled
+fn apply_icon(
+    desktop_notif: &mut notify_rust::Notification,
+    icon: Option<&gotify::Icon>,
+    #[cfg_attr(
+        not(all(unix, not(target_os = "macos"))),
+        expect(
+            unused_variables,
+            reason = "Image data needs XDG notification server features"
+        )
+    )]
+    image_data: bool,
+) -> anyhow::Result<()> {
+    match icon {
+        Some(gotify::Icon::File(img_filepath)) => {
+            #[cfg(all(unix, not(target_os = "macos")))]
+            if image_data {
+                match notify_rust::Image::open(img_filepath) {
+                    Ok(img) => {
+                        desktop_notif.image_data(img);
+                    }
+                    Err(err) => log::warn!("Failed to load image {img_filepath:?}: {err}"),
+                }
+            }
+            desktop_notif.icon(
+                img_filepath
+                    .to_str()
+                    .ok_or_else(|| anyhow::anyhow!("Unable to convert path to string"))?,
+            );
+        }
+        Some(gotify::Icon::Name(icon_name)) => {
+            desktop_notif.icon(icon_name);
+        }
+        None => {
+            desktop_notif.icon(DESKTOP_ENTRY_NAME);
+        }
+    }
+    Ok(())
+}
+
+/// Can the notification server play the sound from hints? Only XDG ones can play files
+fn server_plays(sound: &config::Sound, caps: Capabilities) -> bool {
+    caps.sound
+        && (cfg!(all(unix, not(target_os = "macos"))) || matches!(sound, config::Sound::Name(_)))
+}
+
+/// Set notification sound hint, if the notification server can play it
+fn apply_sound(
+    desktop_notif: &mut notify_rust::Notification,
+    sound: &config::Sound,
+    server_plays: bool,
+) {
+    match sound {
+        config::Sound::Name(name) if server_plays => {
+            desktop_notif.sound_name(name);
+        }
+        #[cfg(all(unix, not(target_os = "macos")))]
+        config::Sound::File(filepath) if server_plays => {
+            desktop_notif.hint(notify_rust::Hint::SoundFile(
+                filepath.to_string_lossy().into_owned(),
+            ));
+        }
+        config::Sound::Name(_) | config::Sound::File(_) => {}
+    }
+}
+
+/// Play sound file with the sound command, for backends that can not play it
+fn play_local_sound(sound: &config::Sound, sound_command: Option<&str>) {
+    let filepath = match sound {
+        config::Sound::File(filepath) => filepath,
+        config::Sound::Name(name) => {
+            log::warn!("Notification backend does not support sounds, {name:?} is not played");
+            return;
+        }
+    };
+    let Some(sound_command) = sound_command else {
+        log::warn!("Sound file {filepath:?} can only be played with a sound command");
+        return;
+    };
+    if let Err(err) = play_sound(sound_command, filepath) {
+        log::warn!("Failed to play sound {filepath:?}: {err:?}");
+    }
+}
+
+/// Play sound file with player command, without waiting for it to finish
+fn play_sound(command: &str, filepath: &Path) -> anyhow::Result<()> {
+    let args = shlex::split(command)
+        .ok_or_else(|| anyhow::anyhow!("Failed to parse command {command:?}"))?;
+    let (program, args) = args
+        .split_first()
+        .ok_or_else(|| anyhow::anyhow!("Empty command"))?;
+    log::debug!("Playing {filepath:?} with {command:?}");
+    let mut child = Command::new(program)
+        .args(args)
+        .arg(filepath)
+        .stdin(Stdio::null())
+        .spawn()?;
+    // Reap process in the background
+    thread::Builder::new()
+        .name("sound-player".to_owned())
+        .spawn(move || match child.wait() {
+            Ok(status) if !status.success() => {
+                log::warn!("Sound command failed with status {status:?}");
+            }
+            Ok(_) => {}
+            Err(err) => log::warn!("Failed to wait for sound command: {err}"),
+        })?;
+    Ok(())
+}
+
+/// Notifications through the XDG desktop portal, for sandboxed environments like Flatpak.
+/// Action buttons are not supported
+#[cfg(all(unix, not(target_os = "macos")))]
+struct Portal {
+    /// Session bus connection
+    connection: zbus::blocking::Connection,
+    /// Command to play sound files with
+    sound_command: Option<String>,
+    /// Prefix of notification ids, unique to this run so that notifications
+    /// of a previous run are not replaced
+    id_prefix: String,
+    /// Id of the last notification
+    last_id: u32,
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+impl Portal {
+    /// Connect to the session bus
+    fn new(cfg: &config::NotificationConfig) -> anyhow::Result<Self> {
+        Ok(Self {
+            connection: zbus::blocking::Connection::session()?,
+            sound_command: cfg.sound_command.clone(),
+            id_prefix: format!(
+                "{}-{}",
+                DESKTOP_ENTRY_NAME,
+                jiff::Timestamp::now().as_millisecond()
+            ),
+            last_id: 0,
+        })
+    }
+
+    /// Portal notification id
+    fn portal_id(&self, notif_id: u32) -> String {
+        format!("{}-{notif_id}", self.id_prefix)
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+impl Notifier for Portal {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::NONE
+    }
+
+    fn show(&mut self, notif: &Notification) -> anyhow::Result<Option<u32>> {
+        use zbus::zvariant::Value;
+
+        // Notifications with the same id replace each other
+        let notif_id = notif.replaces_id.unwrap_or_else(|| {
+            self.last_id = self.last_id.wrapping_add(1);
+            self.last_id
+        });
+        let priority = match notif.urgency {
+            Some(config::Urgency::Low) => "low",
+            Some(config::Urgency::Normal) | None => "normal",
+            Some(config::Urgency::Critical) => "urgent",
+        };
+        let mut fields: HashMap<&str, Value<'_>> = HashMap::from([
+            ("title", Value::from(notif.summary.as_str())),
+            ("body", Value::from(notif.body.as_str())),
+            ("priority", Value::from(priority)),
+        ]);
+        // Icons are serialized GIcon values
+        let icon = match &notif.icon {
+            Some(gotify::Icon::File(img_filepath)) => match fs::read(img_filepath) {
+                Ok(data) => Some(Value::new(("bytes", Value::new(data)))),
+                Err(err) => {
+                    log::warn!("Failed to read image {img_filepath:?}: {err}");
+                    None
+                }
+            },
+            Some(gotify::Icon::Name(icon_name)) => {
+                Some(Value::new(("themed", Value::new(vec![icon_name.as_str()]))))
+            }
+            None => Some(Value::new(("themed", Value::new(vec![DESKTOP_ENTRY_NAME])))),
+        };
+        if let Some(icon) = icon {
+            fields.insert("icon", icon);
+        }
+
+        self.connection.call_method(
+            Some(PORTAL_BUS_NAME),
+            PORTAL_PATH,
+            Some(PORTAL_INTERFACE),
+            "AddNotification",
+            &(self.portal_id(notif_id), fields),
+        )?;
+
+        if let Some(sound) = &notif.sound {
+            play_local_sound(sound, self.sound_command.as_deref());
+        }
+
+        Ok(Some(notif_id))
+    }
+
+    fn close(&mut self, notif_id: u32) -> anyhow::Result<()> {
+        self.connection.call_method(
+            Some(PORTAL_BUS_NAME),
+            PORTAL_PATH,
+            Some(PORTAL_INTERFACE),
+            "RemoveNotification",
+            &(self.portal_id(notif_id),),
+        )?;
+        Ok(())
+    }
+}
+
+/// Notifications as JSON lines on standard output, for other programs to consume
+struct Stdout;
+
+impl Notifier for Stdout {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::NONE
+    }
+
+    fn show(&mut self, notif: &Notification) -> anyhow::Result<Option<u32>> {
+        let mut line = serde_json::to_string(notif)?;
+        line.push('\n');
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(line.as_bytes())?;
+        stdout.flush()?;
+        Ok(None)
+    }
+}
+
+/// No notification, for headless machines that only run actions
+struct Noop;
+
+impl Notifier for Noop {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::NONE
+    }
+
+    fn show(&mut self, notif: &Notification) -> anyhow::Result<Option<u32>> {
+        log::debug!("Not showing notification {:?}", notif.summary);
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_capabilities() {
+        let names = ["body", "body-markup", "icon-static", "persistence"].map(str::to_owned);
+        assert_eq!(
+            Capabilities::from_names(&names),
+            Capabilities {
+                actions: false,
+                body_markup: true,
+                body_images: false,
+                images: true,
+                sound: false,
+            }
+        );
+    }
+
+    #[test]
+    fn notification_json() {
+        let notif = Notification {
+            replaces_id: Some(3),
+            app_name: "Backups".to_owned(),
+            summary: "Job failed".to_owned(),
+            body: "Disk full".to_owned(),
+            icon: Some(gotify::Icon::Name("drive-harddisk".to_owned())),
+            urgency: Some(config::Urgency::Critical),
+            sound: Some(config::Sound::Name("bell".to_owned())),
+            buttons: vec![Button {
+                action: config::NotificationAction::Delete,
+                label: "Delete on server".to_owned(),
+            }],
+            ..Notification::default()
+        };
+        assert_eq!(
+            serde_json::to_value(&notif).unwrap(),
+            serde_json::json!({
+                "app_name": "Backups",
+                "summary": "Job failed",
+                "body": "Disk full",
+                "icon": {"name": "drive-harddisk"},
+                "urgency": "critical",
+                "category": null,
+                "timeout": null,
+                "sound": "bell",
+                "buttons": [{"action": "delete", "label": "Delete on server"}],
+                "resident": false
+            })
+        );
+    }
+}
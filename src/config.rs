@@ -1,7 +1,9 @@
 //! Local configuration
 
 use std::{
+    collections::BTreeMap,
     fs,
+    path::PathBuf,
     process::{Command, Stdio},
 };
 
@@ -18,9 +20,13 @@ pub(crate) struct Config {
     /// Action specific local configuration
     #[serde(default)]
     pub action: ActionConfig,
+
+    /// Per application local configuration
+    #[serde(default)]
+    pub apps: Vec<AppConfig>,
 }
 
-/// A token either as a string, or a command to run to get it
+/// A token or other secret value, either as a string, or a command to run to get it
 #[derive(Clone, Debug, serde::Deserialize)]
 #[cfg_attr(test, derive(Eq, PartialEq))]
 #[serde(rename_all = "snake_case")]
@@ -33,11 +39,11 @@ pub(crate) enum TokenSource {
 }
 
 impl TokenSource {
-    /// Get token string, by running command if needed
+    /// Get token or value string, by running command if needed
     pub(crate) fn fetch(&self) -> anyhow::Result<String> {
         match self {
             TokenSource::Command(cmd) => {
-                log::info!("Running command {cmd:?} to fetch token");
+                log::info!("Running command {cmd:?} to fetch value");
                 let cmd = shlex::split(cmd)
                     .ok_or_else(|| anyhow::anyhow!("Failed to parse command {cmd:?}"))?;
                 let output = Command::new(
@@ -72,15 +78,251 @@ pub(crate) struct GotifyConfig {
     /// Should messages be deleted on reception?
     #[serde(default)]
     pub auto_delete: bool,
+    /// Additional HTTP headers sent with every request, for reverse proxies
+    #[serde(default)]
+    pub headers: BTreeMap<String, TokenSource>,
+    /// HTTP basic authentication, for reverse proxies
+    pub basic_auth: Option<BasicAuthConfig>,
+}
+
+/// HTTP basic authentication credentials
+#[derive(Clone, Debug, serde::Deserialize)]
+pub(crate) struct BasicAuthConfig {
+    /// User name
+    pub username: String,
+    /// Password
+    pub password: TokenSource,
 }
 
 /// Notification specific local configuration
-#[derive(Debug, Default, serde::Deserialize)]
+#[derive(Clone, Debug, Default, serde::Deserialize)]
+#[serde(default)]
 pub(crate) struct NotificationConfig {
+    /// Backend to show notifications with
+    pub backend: Backend,
     /// Minimum priority below which to disable message notification
     pub min_priority: i64,
+    /// Send icon as raw pixel data, for notification servers that can not load icon files
+    pub image_data: bool,
+    /// Notification settings by message priority range, first matching range wins
+    pub priorities: Vec<PriorityConfig>,
+    /// Time window in seconds during which messages from the same app update the previous
+    /// notification instead of showing a new one, 0 to disable
+    pub coalesce_window: u64,
+    /// Notification rate limits
+    pub rate_limit: RateLimitConfig,
+    /// Identical message detection
+    pub dedupe: DedupeConfig,
+    /// Command to play sound files with, for notification servers that do not support sound hints
+    pub sound_command: Option<String>,
+    /// Notification action buttons
+    pub actions: Vec<NotificationAction>,
+    /// Snooze duration in minutes, defaults to 15
+    pub snooze_minutes: Option<u64>,
+    /// Command to write text from its standard input to the clipboard, platform default if unset
+    pub clipboard_command: Option<String>,
+    /// Repeat-until-acknowledged alerts
+    pub alert: AlertConfig,
+    /// Summary template, with `{app}`, `{title}`, `{text}`, `{priority}`, `{date}`, `{age}`
+    /// and `{server}` placeholders
+    pub summary: Option<String>,
+    /// Body template, with the same placeholders as the summary one
+    pub body: Option<String>,
+}
+
+/// Repeat-until-acknowledged alerts
+#[derive(Clone, Debug, Default, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct AlertConfig {
+    /// Minimum priority of messages shown as alerts, alerts are disabled if unset
+    pub min_priority: Option<i64>,
+    /// Delay in minutes between reminders, defaults to 5
+    pub repeat_minutes: Option<u64>,
+    /// Command to run if an alert is not acknowledged in time
+    pub escalation_command: Option<String>,
+    /// Delay in minutes before running the escalation command, defaults to 30
+    pub escalation_minutes: Option<u64>,
+}
+
+/// Notification backend
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Backend {
+    /// XDG notification server over D-Bus, or native notifications on macOS
+    #[default]
+    Freedesktop,
+    /// XDG desktop portal, for sandboxed environments like Flatpak
+    Portal,
+    /// JSON lines on standard output
+    Stdout,
+    /// No notification, only run actions
+    #[serde(rename = "none")]
+    Noop,
+}
+
+/// Notification action button
+#[derive(Clone, Copy, Debug, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum NotificationAction {
+    /// Delete message on server
+    Delete,
+    /// Show notification again later
+    Snooze,
+    /// Copy message text to clipboard
+    Copy,
+    /// Open server web UI
+    Open,
+    /// Acknowledge alert
+    Acknowledge,
+}
+
+impl NotificationAction {
+    /// All actions
+    const ALL: [Self; 5] = [
+        Self::Delete,
+        Self::Snooze,
+        Self::Copy,
+        Self::Open,
+        Self::Acknowledge,
+    ];
+
+    /// Action key sent to the notification server
+    pub(crate) fn key(self) -> &'static str {
+        match self {
+            Self::Delete => "delete",
+            Self::Snooze => "snooze",
+            Self::Copy => "copy",
+            Self::Open => "open",
+            Self::Acknowledge => "acknowledge",
+        }
+    }
+
+    /// Get action from its key
+    pub(crate) fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|a| a.key() == key)
+    }
+}
+
+/// Notification sound, either a sound theme name, or a sound file
+#[derive(Clone, Debug, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Sound {
+    /// Sound file path
+    File(PathBuf),
+    /// Sound theme name
+    #[serde(untagged)]
+    Name(String),
+}
+
+/// Identical message detection
+#[derive(Clone, Debug, Default, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct DedupeConfig {
+    /// Time window in seconds during which an identical message from the same app does not show
+    /// a new notification, restarted on each repetition, 0 to disable
+    pub window: u64,
+    /// Update the notification with a repetition counter
+    pub counter: bool,
+}
+
+/// Notification rate limits, unlimited if unset
+#[derive(Clone, Debug, Default, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RateLimitConfig {
+    /// Limit for all apps
+    pub global: Option<BucketConfig>,
+    /// Limit for each app
+    pub per_app: Option<BucketConfig>,
+}
+
+/// Token bucket rate limit
+#[derive(Clone, Debug, serde::Deserialize)]
+pub(crate) struct BucketConfig {
+    /// Maximum number of notifications in a burst
+    pub burst: u32,
+    /// Sustained number of notifications per minute
+    pub per_minute: u32,
+}
+
+impl NotificationConfig {
+    /// Get notification settings for a message priority, if any range matches it
+    pub(crate) fn priority(&self, priority: i64) -> Option<&PriorityConfig> {
+        let table = if self.priorities.is_empty() {
+            &DEFAULT_PRIORITIES[..]
+        } else {
+            &self.priorities
+        };
+        table.iter().find(|p| p.contains(priority))
+    }
+}
+
+/// Notification urgency level
+#[derive(Clone, Copy, Debug, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Urgency {
+    /// Low urgency
+    Low,
+    /// Normal urgency
+    Normal,
+    /// Critical urgency, notification usually does not expire
+    Critical,
+}
+
+/// Notification settings for a range of message priorities
+#[derive(Clone, Debug, Default, serde::Deserialize)]
+#[cfg_attr(test, derive(Eq, PartialEq))]
+#[serde(deny_unknown_fields)]
+pub(crate) struct PriorityConfig {
+    /// Minimum priority, inclusive, unbounded if unset
+    pub min: Option<i64>,
+    /// Maximum priority, inclusive, unbounded if unset
+    pub max: Option<i64>,
+    /// Notification urgency
+    pub urgency: Option<Urgency>,
+    /// Expiration timeout in milliseconds, 0 to never expire, server default if unset
+    pub timeout: Option<u32>,
+    /// Sound to play
+    pub sound: Option<Sound>,
+    /// Notification category, like "im.received" or "device.error"
+    pub category: Option<String>,
+}
+
+impl PriorityConfig {
+    /// Does this range include the given priority?
+    fn contains(&self, priority: i64) -> bool {
+        self.min.is_none_or(|m| priority >= m) && self.max.is_none_or(|m| priority <= m)
+    }
 }
 
+/// Priority ranges used if none are configured, following Gotify clients conventions,
+/// with negative and high priorities falling in the lowest and highest ranges
+const DEFAULT_PRIORITIES: [PriorityConfig; 3] = [
+    PriorityConfig {
+        min: None,
+        max: Some(3),
+        urgency: Some(Urgency::Low),
+        timeout: None,
+        sound: None,
+        category: None,
+    },
+    PriorityConfig {
+        min: Some(4),
+        max: Some(7),
+        urgency: Some(Urgency::Normal),
+        timeout: None,
+        sound: None,
+        category: None,
+    },
+    PriorityConfig {
+        min: Some(8),
+        max: None,
+        urgency: Some(Urgency::Critical),
+        timeout: None,
+        sound: None,
+        category: None,
+    },
+];
+
 /// Action specific local configuration
 #[derive(Debug, Default, serde::Deserialize)]
 pub(crate) struct ActionConfig {
@@ -88,6 +330,41 @@ pub(crate) struct ActionConfig {
     pub on_msg_command: Option<String>,
 }
 
+/// Per application local configuration
+#[derive(Clone, Debug, serde::Deserialize)]
+pub(crate) struct AppConfig {
+    /// Gotify app id to match
+    pub id: Option<i64>,
+    /// Gotify app name to match
+    pub name: Option<String>,
+    /// Icon theme name, or absolute image filepath, to use instead of the Gotify app image
+    pub icon: Option<String>,
+    /// Name to display instead of the Gotify app name
+    pub display_name: Option<String>,
+    /// Sound to play, instead of the one of the message priority range
+    pub sound: Option<Sound>,
+    /// Summary template, instead of the global one
+    pub summary: Option<String>,
+    /// Body template, instead of the global one
+    pub body: Option<String>,
+    /// Trust the app to send body markup, instead of escaping it
+    #[serde(default)]
+    pub markup: bool,
+}
+
+impl AppConfig {
+    /// Does this configuration apply to the given Gotify app?
+    pub(crate) fn matches(&self, id: i64, name: Option<&str>) -> bool {
+        self.id.map_or_else(
+            || self.name.is_some() && self.name.as_deref() == name,
+            |i| i == id,
+        )
+    }
+}
+
+/// Maximum duration in minutes, of a year
+pub(crate) const MAX_MINUTES: u64 = 365 * 24 * 60;
+
 /// Parse local configuration
 pub(crate) fn parse() -> anyhow::Result<Config> {
     let binary_name = env!("CARGO_PKG_NAME");
@@ -100,8 +377,32 @@ pub(crate) fn parse() -> anyhow::Result<Config> {
     let toml_data = fs::read_to_string(config_filepath)?;
     log::trace!("Config data: {toml_data:?}");
 
-    let config = toml::from_str(&toml_data)?;
+    let config: Config = toml::from_str(&toml_data)?;
     log::trace!("Config: {config:?}");
+    for range in &config.notification.priorities {
+        if let (Some(min), Some(max)) = (range.min, range.max) {
+            anyhow::ensure!(min <= max, "Invalid priority range {min}..={max}");
+        }
+    }
+    for app in &config.apps {
+        anyhow::ensure!(
+            app.id.is_some() || app.name.is_some(),
+            "App configuration {app:?} needs an id or name to match"
+        );
+    }
+    let alert = &config.notification.alert;
+    for (name, minutes) in [
+        ("snooze", config.notification.snooze_minutes),
+        ("alert repeat", alert.repeat_minutes),
+        ("alert escalation", alert.escalation_minutes),
+    ] {
+        if let Some(minutes) = minutes {
+            anyhow::ensure!(
+                (1..=MAX_MINUTES).contains(&minutes),
+                "Invalid {name} delay of {minutes} minutes, expected 1 to {MAX_MINUTES}"
+            );
+        }
+    }
     Ok(config)
 }
 
@@ -134,6 +435,135 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_headers() {
+        let cfg = toml::from_str::<GotifyConfig>(
+            r#"
+url = "wss://example.com"
+token = "abcdef1234"
+
+[headers]
+CF-Access-Client-Id = "id1234"
+CF-Access-Client-Secret = { command = "echo secret" }
+
+[basic_auth]
+username = "user"
+password = "pass"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.headers.into_iter().collect::<Vec<_>>(),
+            vec![
+                (
+                    "CF-Access-Client-Id".to_owned(),
+                    TokenSource::Plain("id1234".to_owned())
+                ),
+                (
+                    "CF-Access-Client-Secret".to_owned(),
+                    TokenSource::Command("echo secret".to_owned())
+                ),
+            ]
+        );
+        let basic_auth = cfg.basic_auth.unwrap();
+        assert_eq!(basic_auth.username, "user");
+        assert_eq!(basic_auth.password, TokenSource::Plain("pass".to_owned()));
+    }
+
+    #[test]
+    fn priority_ranges() {
+        let default_cfg = NotificationConfig::default();
+        for (priority, urgency) in [
+            (-5, Urgency::Low),
+            (0, Urgency::Low),
+            (4, Urgency::Normal),
+            (8, Urgency::Critical),
+            (20, Urgency::Critical),
+        ] {
+            assert_eq!(
+                default_cfg.priority(priority).unwrap().urgency,
+                Some(urgency)
+            );
+        }
+
+        let cfg = toml::from_str::<NotificationConfig>(
+            r#"
+[[priorities]]
+max = 9
+urgency = "normal"
+
+[[priorities]]
+min = 10
+max = 15
+urgency = "critical"
+timeout = 0
+sound = "alarm-clock-elapsed"
+category = "device.error"
+"#,
+        )
+        .unwrap();
+        assert_eq!(cfg.priority(-1).unwrap().urgency, Some(Urgency::Normal));
+        assert_eq!(
+            cfg.priority(12),
+            Some(&PriorityConfig {
+                min: Some(10),
+                max: Some(15),
+                urgency: Some(Urgency::Critical),
+                timeout: Some(0),
+                sound: Some(Sound::Name("alarm-clock-elapsed".to_owned())),
+                category: Some("device.error".to_owned()),
+            })
+        );
+        assert!(cfg.priority(16).is_none());
+    }
+
+    #[test]
+    fn notification_actions() {
+        let cfg = toml::from_str::<NotificationConfig>(r#"actions = ["delete", "open"]"#).unwrap();
+        assert_eq!(
+            cfg.actions,
+            vec![NotificationAction::Delete, NotificationAction::Open]
+        );
+        for action in NotificationAction::ALL {
+            assert_eq!(NotificationAction::from_key(action.key()), Some(action));
+        }
+        assert!(NotificationAction::from_key("default").is_none());
+    }
+
+    #[test]
+    fn app_matches() {
+        #[derive(Debug, serde::Deserialize)]
+        struct TestConfig {
+            apps: Vec<AppConfig>,
+        }
+
+        let apps = toml::from_str::<TestConfig>(
+            r#"
+[[apps]]
+id = 3
+icon = "dialog-warning"
+
+[[apps]]
+name = "Backups"
+display_name = "Backup jobs"
+sound = { file = "/usr/share/sounds/freedesktop/stereo/complete.oga" }
+"#,
+        )
+        .unwrap()
+        .apps;
+        assert_eq!(
+            apps[1].sound,
+            Some(Sound::File(
+                "/usr/share/sounds/freedesktop/stereo/complete.oga".into()
+            ))
+        );
+        assert!(apps[0].matches(3, None));
+        assert!(!apps[0].matches(4, Some("Backups")));
+        assert!(apps[1].matches(4, Some("Backups")));
+        assert!(!apps[1].matches(4, Some("Other")));
+        assert!(!apps[1].matches(4, None));
+    }
+
     #[test]
     fn fetch_token() {
         assert_eq!(
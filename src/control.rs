@@ -0,0 +1,188 @@
+//! Local control socket, to send commands to the running daemon
+
+use std::{
+    fs,
+    io::{self, BufRead as _, BufReader, Write as _},
+    os::unix::net::{UnixListener, UnixStream},
+    path::PathBuf,
+    thread,
+    time::Duration,
+};
+
+use anyhow::Context as _;
+
+use crate::{actions, config};
+
+/// Maximum time to wait for a client to send its command, or for the daemon to execute it
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Command sent to the daemon
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Command {
+    /// Snooze message, for the configured duration if unset
+    Snooze {
+        /// Gotify message id
+        msg_id: i64,
+        /// Snooze duration
+        duration: Option<Duration>,
+    },
+    /// Acknowledge alert of a message
+    Ack {
+        /// Gotify message id
+        msg_id: i64,
+    },
+}
+
+impl Command {
+    /// Parse command line, as sent on the socket
+    fn parse(line: &str) -> anyhow::Result<Self> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["snooze", msg_id, args @ ..] if args.len() <= 1 => Ok(Self::Snooze {
+                msg_id: msg_id
+                    .parse()
+                    .with_context(|| format!("Invalid message id {msg_id:?}"))?,
+                duration: args
+                    .first()
+                    .map(|m| {
+                        m.parse::<u64>()
+                            .ok()
+                            .filter(|m| (1..=config::MAX_MINUTES).contains(m))
+                            .map(|m| Duration::from_secs(m * 60))
+                            .ok_or_else(|| {
+                                anyhow::anyhow!(
+                                    "Invalid minutes {m:?}, expected 1 to {}",
+                                    config::MAX_MINUTES
+                                )
+                            })
+                    })
+                    .transpose()?,
+            }),
+            ["ack", msg_id] => Ok(Self::Ack {
+                msg_id: msg_id
+                    .parse()
+                    .with_context(|| format!("Invalid message id {msg_id:?}"))?,
+            }),
+            _ => anyhow::bail!("Invalid command {line:?}"),
+        }
+    }
+}
+
+/// Get control socket path
+fn socket_path() -> anyhow::Result<PathBuf> {
+    let xdg_dirs = xdg::BaseDirectories::with_prefix(env!("CARGO_PKG_NAME"));
+    xdg_dirs
+        .place_runtime_file("control.sock")
+        .context("Failed to create runtime directory")
+}
+
+/// Listen for commands in a background thread, and forward them to the main loop
+pub(crate) fn listen(sender: actions::CommandSender) -> anyhow::Result<()> {
+    let path = socket_path()?;
+    // Remove socket left by a previous instance, unless it is still running
+    match UnixStream::connect(&path) {
+        Ok(_) => anyhow::bail!("Another instance is listening on {path:?}"),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => {
+            log::debug!("Removing stale socket {path:?}");
+            fs::remove_file(&path)?;
+        }
+        Err(err) => {
+            return Err(anyhow::Error::from(err)
+                .context(format!("Failed to check existing socket {path:?}")));
+        }
+    }
+    let listener = UnixListener::bind(&path)?;
+    log::debug!("Listening for commands on {path:?}");
+    thread::Builder::new()
+        .name("control".to_owned())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let res = stream
+                    .map_err(anyhow::Error::from)
+                    .and_then(|s| handle_client(&s, &sender));
+                if let Err(err) = res {
+                    log::warn!("Control client failed: {err:?}");
+                }
+            }
+        })?;
+    Ok(())
+}
+
+/// Read command from client, and reply with its result
+fn handle_client(stream: &UnixStream, sender: &actions::CommandSender) -> anyhow::Result<()> {
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    let res = Command::parse(&line).and_then(|command| {
+        log::info!("Got command {command:?}");
+        sender
+            .send(command)?
+            .recv_timeout(IO_TIMEOUT)
+            .context("No reply from main loop")?
+            .map_err(anyhow::Error::msg)
+    });
+    let reply = match res {
+        Ok(()) => "ok\n".to_owned(),
+        Err(err) => format!("error: {err:#}\n"),
+    };
+    let mut writer = stream;
+    writer.write_all(reply.as_bytes())?;
+    Ok(())
+}
+
+/// Is a daemon listening on the control socket?
+pub(crate) fn daemon_running() -> bool {
+    socket_path().is_ok_and(|path| UnixStream::connect(path).is_ok())
+}
+
+/// Send command to the running daemon, and wait for its result
+pub(crate) fn send(line: &str) -> anyhow::Result<()> {
+    let path = socket_path()?;
+    let mut stream = UnixStream::connect(&path)
+        .with_context(|| format!("Failed to connect to daemon socket {path:?}"))?;
+    stream.set_read_timeout(Some(IO_TIMEOUT * 2))?;
+    stream.write_all(format!("{line}\n").as_bytes())?;
+    let mut reply = String::new();
+    BufReader::new(&stream).read_line(&mut reply)?;
+    match reply.trim_end().strip_prefix("error: ") {
+        Some(err) => anyhow::bail!("Daemon error: {err}"),
+        None if reply.trim_end() == "ok" => Ok(()),
+        None => anyhow::bail!("Unexpected reply {reply:?}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_command() {
+        assert_eq!(
+            Command::parse("snooze 12\n").unwrap(),
+            Command::Snooze {
+                msg_id: 12,
+                duration: None
+            }
+        );
+        assert_eq!(
+            Command::parse("snooze 12 30").unwrap(),
+            Command::Snooze {
+                msg_id: 12,
+                duration: Some(Duration::from_secs(30 * 60))
+            }
+        );
+        assert!(Command::parse("snooze").is_err());
+        assert!(Command::parse("snooze abc").is_err());
+        assert!(Command::parse("snooze 12 30 40").is_err());
+        assert!(Command::parse("snooze 12 0").is_err());
+        assert!(Command::parse("snooze 12 18446744073709551615").is_err());
+        assert_eq!(
+            Command::parse("ack 12").unwrap(),
+            Command::Ack { msg_id: 12 }
+        );
+        assert!(Command::parse("ack 12 30").is_err());
+        assert!(Command::parse("delete 12").is_err());
+    }
+}
@@ -0,0 +1,125 @@
+//! Detection of identical messages sent repeatedly
+
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    hash::{DefaultHasher, Hash as _, Hasher as _},
+    time::{Duration, Instant},
+};
+
+use crate::gotify;
+
+/// Message seen recently
+struct Seen {
+    /// Number of times the message was repeated after the first one
+    repeats: usize,
+    /// When the message was last seen
+    last_at: Instant,
+    /// Id of the notification shown for it, if the notification server supports updating it
+    notif_id: Option<u32>,
+}
+
+/// Recently seen messages, identified by app id, title and text
+pub(crate) struct Deduper {
+    /// Duration after which a message is no longer considered a duplicate, zero to disable
+    window: Duration,
+    /// Seen messages by key hash
+    seen: HashMap<u64, Seen>,
+}
+
+impl Deduper {
+    /// Create empty deduper
+    pub(crate) fn new(window: Duration) -> Self {
+        Self {
+            window,
+            seen: HashMap::new(),
+        }
+    }
+
+    /// Key identifying identical messages
+    fn key(msg: &gotify::Message) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        (msg.appid, &msg.title, &msg.text).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Record message, and return how many times it was repeated within the window,
+    /// 0 if it is new. The window restarts on each repetition
+    pub(crate) fn check(&mut self, msg: &gotify::Message, now: Instant) -> usize {
+        if self.window.is_zero() {
+            return 0;
+        }
+        let window = self.window;
+        self.seen
+            .retain(|_, s| now.saturating_duration_since(s.last_at) < window);
+        match self.seen.entry(Self::key(msg)) {
+            Entry::Occupied(mut entry) => {
+                let seen = entry.get_mut();
+                seen.repeats += 1;
+                seen.last_at = now;
+                seen.repeats
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(Seen {
+                    repeats: 0,
+                    last_at: now,
+                    notif_id: None,
+                });
+                0
+            }
+        }
+    }
+
+    /// Get id of the notification shown for a message
+    pub(crate) fn notif_id(&self, msg: &gotify::Message) -> Option<u32> {
+        self.seen.get(&Self::key(msg)).and_then(|s| s.notif_id)
+    }
+
+    /// Record id of the notification shown for a message
+    pub(crate) fn set_notif_id(&mut self, msg: &gotify::Message, notif_id: u32) {
+        if let Some(seen) = self.seen.get_mut(&Self::key(msg)) {
+            seen.notif_id = Some(notif_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(appid: i64, text: &str) -> gotify::Message {
+        gotify::Message {
+            text: text.to_owned(),
+            ..gotify::test_message(1, appid, 5)
+        }
+    }
+
+    #[test]
+    fn repeated_messages() {
+        let window = Duration::from_secs(60);
+        let mut deduper = Deduper::new(window);
+        let now = Instant::now();
+        assert_eq!(deduper.check(&message(1, "disk full"), now), 0);
+        deduper.set_notif_id(&message(1, "disk full"), 42);
+        assert_eq!(deduper.check(&message(2, "disk full"), now), 0);
+        assert_eq!(deduper.check(&message(1, "disk ok"), now), 0);
+
+        let later = now + Duration::from_secs(50);
+        assert_eq!(deduper.check(&message(1, "disk full"), later), 1);
+        assert_eq!(deduper.notif_id(&message(1, "disk full")), Some(42));
+        let even_later = later + Duration::from_secs(50);
+        assert_eq!(deduper.check(&message(1, "disk full"), even_later), 2);
+        assert_eq!(deduper.check(&message(2, "disk full"), even_later), 0);
+
+        let much_later = even_later + window;
+        assert_eq!(deduper.check(&message(1, "disk full"), much_later), 0);
+        assert!(deduper.notif_id(&message(1, "disk full")).is_none());
+    }
+
+    #[test]
+    fn disabled() {
+        let mut deduper = Deduper::new(Duration::ZERO);
+        let now = Instant::now();
+        assert_eq!(deduper.check(&message(1, "disk full"), now), 0);
+        assert_eq!(deduper.check(&message(1, "disk full"), now), 0);
+    }
+}
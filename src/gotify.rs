@@ -2,54 +2,112 @@
 
 use std::{
     cell::RefCell,
-    collections::HashMap,
-    fs::{self},
     io::ErrorKind,
     os::unix::io::AsRawFd as _,
     path::{Path, PathBuf},
     rc::Rc,
-    sync::LazyLock,
-    time::Duration,
+    sync::{Arc, LazyLock},
+    time::{Duration, Instant},
 };
 
+use anyhow::Context as _;
 use backon::BlockingRetryable as _;
-use tungstenite::{client::IntoClientRequest as _, error::ProtocolError, http::HeaderValue};
+use base64::Engine as _;
+use tungstenite::{
+    client::IntoClientRequest as _,
+    error::ProtocolError,
+    http::{HeaderMap, HeaderName, HeaderValue, header},
+};
 
-use crate::config;
+use crate::{apps, backend, config, icons, notif};
 
 /// Error when socket needs reconnect
 #[derive(thiserror::Error, Debug)]
-#[error(transparent)]
 pub(crate) enum NeedsReconnect {
-    /// Inner poll error
+    /// Inner poll or socket error
+    #[error(transparent)]
     Io(#[from] std::io::Error),
     /// Protocol disconnect
+    #[error(transparent)]
     Protocol(#[from] ProtocolError),
+    /// Server closed the connection
+    #[error("Server closed connection ({0})")]
+    Closed(String),
+    /// Server did not answer ping in time
+    #[error("No pong received from server after {0:?}")]
+    PongTimeout(Duration),
 }
 
+/// Poller token for websocket events
+const WS_TOKEN: mio::Token = mio::Token(0);
+
+/// Poller token for wake ups
+const WAKE_TOKEN: mio::Token = mio::Token(1);
+
+/// Delay without any data received after which we ping the server
+const PING_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Delay after a ping during which the server must answer
+const PONG_TIMEOUT: Duration = Duration::from_secs(15);
+
 /// Gotify client state
 pub(crate) struct Client {
     /// Websocket client, if connected
     ws: WebSocket,
     /// Socket poller
     poller: mio::Poll,
-    /// Gotify API token
-    token: String,
-    /// HTTP client (non websocket)
-    #[expect(clippy::struct_field_names)]
-    http_client: ureq::Agent,
-    /// Gotify HTTP(S) URL
-    http_url: url::Url,
-    /// App image cache
-    app_imgs: HashMap<i64, Option<PathBuf>>,
+    /// Waker to interrupt polling
+    waker: Arc<mio::Waker>,
+    /// REST API client
+    api: Api,
+    /// Application metadata, kept across reconnects
+    apps: Rc<RefCell<apps::Registry>>,
+    /// Background app image downloader
+    downloader: icons::Downloader,
     /// XDG dirs
     xdg_dirs: xdg::BaseDirectories,
     /// Last received Gotify message id
     last_msg_id: Rc<RefCell<Option<i64>>>,
+    /// Time of last data received from the server
+    last_rx: Instant,
+    /// Time of the ping we sent and are waiting a pong for
+    ping_tx: Option<Instant>,
+}
+
+/// Gotify REST API client
+#[derive(Clone)]
+pub(crate) struct Api {
+    /// HTTP client (non websocket)
+    agent: ureq::Agent,
+    /// Gotify HTTP(S) URL
+    url: url::Url,
+    /// Headers sent with every request (Gotify token, reverse proxy headers...)
+    headers: HeaderMap,
+}
+
+/// Gotify server version information
+#[derive(Debug, serde::Deserialize)]
+struct VersionInfo {
+    /// Version string
+    version: String,
+    /// Git commit
+    commit: String,
+    /// Build date
+    #[serde(rename = "buildDate")]
+    build_date: String,
+}
+
+/// Gotify server health status
+#[derive(Debug, serde::Deserialize)]
+struct Health {
+    /// Global health
+    health: String,
+    /// Database health
+    database: String,
 }
 
 /// Gotify message
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub(crate) struct Message {
     /// Gotify id
     pub id: i64,
@@ -58,15 +116,74 @@ pub(crate) struct Message {
     /// Message text
     #[serde(rename = "message")]
     pub text: String,
-    /// Message title
+    /// Message title, can be empty or omitted
+    #[serde(default)]
     pub title: String,
-    /// Message priority
+    /// Message priority, omitted by some servers when 0
+    #[serde(default)]
     pub priority: i64,
-    /// Message date & time
+    /// Message date & time, empty if omitted
+    #[serde(default)]
     pub date: String,
-    /// App image filepath
+    /// App name
+    #[serde(skip)]
+    pub app_name: Option<String>,
+    /// App icon
+    #[serde(skip)]
+    pub app_icon: Option<Icon>,
+    /// App sound, overriding the priority one
+    #[serde(skip)]
+    pub app_sound: Option<config::Sound>,
+    /// App summary template, overriding the global one
     #[serde(skip)]
-    pub app_img_filepath: Option<PathBuf>,
+    pub app_summary: Option<String>,
+    /// App body template, overriding the global one
+    #[serde(skip)]
+    pub app_body: Option<String>,
+    /// Is the app trusted to send body markup?
+    #[serde(skip)]
+    pub app_markup: bool,
+}
+
+/// Create message for tests, from a test app without configuration
+#[cfg(test)]
+pub(crate) fn test_message(id: i64, appid: i64, priority: i64) -> Message {
+    Message {
+        id,
+        appid,
+        text: "text".to_owned(),
+        title: "title".to_owned(),
+        priority,
+        date: "2024-01-01T00:00:00Z".to_owned(),
+        app_name: None,
+        app_icon: None,
+        app_sound: None,
+        app_summary: None,
+        app_body: None,
+        app_markup: false,
+    }
+}
+
+/// Notification icon
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Icon {
+    /// Local image file
+    File(PathBuf),
+    /// Icon theme name
+    Name(String),
+}
+
+impl From<&str> for Icon {
+    /// Parse configured icon, which is either an absolute filepath or an icon theme name
+    fn from(s: &str) -> Self {
+        let path = Path::new(s);
+        if path.is_absolute() {
+            Self::File(path.to_owned())
+        } else {
+            Self::Name(s.to_owned())
+        }
+    }
 }
 
 /// Gotify message bunch
@@ -79,7 +196,7 @@ pub(crate) struct AllMessages {
 /// Gotify app metadata
 #[derive(serde::Serialize, serde::Deserialize)]
 struct AppInfo {
-    /// unused
+    /// App description
     description: String,
     /// App id
     id: i64,
@@ -96,84 +213,146 @@ struct AppInfo {
 /// HTTP or HTTPS websocket
 type WebSocket = tungstenite::WebSocket<tungstenite::stream::MaybeTlsStream<std::net::TcpStream>>;
 
+/// Oldest server version supporting all the features we use (message extras)
+const MIN_SERVER_VERSION: [u64; 3] = [2, 0, 0];
+
+/// Health status value when everything is fine
+const HEALTH_OK: &str = "green";
+
+/// Maximum time to wait for the health check, so that a stuck proxy does not prevent connecting
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// HTTP method of REST API requests
+#[derive(Clone, Copy, Debug)]
+enum Method {
+    /// Get resource
+    Get,
+    /// Delete resource
+    Delete,
+}
+
 /// HTTP User-Agent string
 static USER_AGENT: LazyLock<String> =
     LazyLock::new(|| format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")));
 
+#[cfg(not(any(
+    feature = "native-tls",
+    feature = "rustls-webpki-roots",
+    feature = "rustls-platform-roots"
+)))]
+compile_error!(
+    "At least one TLS backend feature must be enabled: native-tls, rustls-webpki-roots or rustls-platform-roots"
+);
+
+/// Build TLS config for REST calls, matching the backend tungstenite picks for the websocket
+/// (native-tls takes precedence if several backends are enabled)
+fn tls_config() -> ureq::tls::TlsConfig {
+    let (provider, root_certs) = if cfg!(feature = "native-tls") {
+        (
+            ureq::tls::TlsProvider::NativeTls,
+            ureq::tls::RootCerts::PlatformVerifier,
+        )
+    } else if cfg!(feature = "rustls-platform-roots") {
+        (
+            ureq::tls::TlsProvider::Rustls,
+            ureq::tls::RootCerts::PlatformVerifier,
+        )
+    } else {
+        (ureq::tls::TlsProvider::Rustls, ureq::tls::RootCerts::WebPki)
+    };
+    ureq::tls::TlsConfig::builder()
+        .provider(provider)
+        .root_certs(root_certs)
+        .build()
+}
+
 /// Build the ureq agent for Gotify REST calls.
 fn http_agent() -> ureq::Agent {
     ureq::Agent::config_builder()
-        .tls_config(
-            ureq::tls::TlsConfig::builder()
-                .provider(ureq::tls::TlsProvider::NativeTls)
-                .root_certs(ureq::tls::RootCerts::PlatformVerifier)
-                .build(),
-        )
+        .tls_config(tls_config())
         .user_agent(&*USER_AGENT)
         .proxy(None)
         .build()
         .new_agent()
 }
 
-impl Client {
-    /// Get a connected Gotify client
-    pub(crate) fn connect(
-        cfg: &config::GotifyConfig,
-        token: &str,
-        last_msg_id: Rc<RefCell<Option<i64>>>,
-    ) -> anyhow::Result<Self> {
-        // Init app img cache
-        let app_imgs: HashMap<i64, Option<PathBuf>> = HashMap::new();
-        let binary_name = env!("CARGO_PKG_NAME");
-        let xdg_dirs = xdg::BaseDirectories::with_prefix(binary_name);
+/// Build headers sent with every request: Gotify token, and optional reverse proxy authentication
+pub(crate) fn request_headers(cfg: &config::GotifyConfig) -> anyhow::Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+
+    let mut token = HeaderValue::from_str(&cfg.token.fetch()?).context("Invalid token")?;
+    token.set_sensitive(true);
+    headers.insert("X-Gotify-Key", token);
+
+    if let Some(basic_auth) = &cfg.basic_auth {
+        let credentials = format!("{}:{}", basic_auth.username, basic_auth.password.fetch()?);
+        let mut value = HeaderValue::from_str(&format!(
+            "Basic {}",
+            base64::engine::general_purpose::STANDARD.encode(credentials)
+        ))
+        .context("Invalid basic auth credentials")?;
+        value.set_sensitive(true);
+        headers.insert(header::AUTHORIZATION, value);
+    }
 
-        // HTTP client (non WS)
-        let http_client = http_agent();
-        let mut http_url = cfg.url.clone();
-        let scheme = match http_url.scheme() {
+    for (name, value) in &cfg.headers {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .with_context(|| format!("Invalid header name {name:?}"))?;
+        let mut value = HeaderValue::from_str(&value.fetch()?)
+            .with_context(|| format!("Invalid value for header {name:?}"))?;
+        value.set_sensitive(true);
+        headers.insert(name, value);
+    }
+
+    Ok(headers)
+}
+
+impl Api {
+    /// Build API client for the server with the given websocket URL
+    pub(crate) fn new(ws_url: &url::Url, headers: HeaderMap) -> anyhow::Result<Self> {
+        let mut url = ws_url.clone();
+        let scheme = match url.scheme() {
             "wss" => "https",
             "ws" => "http",
             s => anyhow::bail!("Unexpected scheme {s:?}"),
         };
         #[expect(clippy::unwrap_used)] // We know the scheme is valid here
-        http_url.set_scheme(scheme).unwrap();
-
-        // Connect gotify client, with retries
-        let (ws, poller) = (|| Self::try_connect(&cfg.url, token))
-            .retry(
-                backon::ExponentialBuilder::default()
-                    .with_factor(1.5)
-                    .with_min_delay(Duration::from_millis(250))
-                    .with_max_delay(Duration::from_secs(60))
-                    .without_max_times(),
-            )
-            .notify(|err, dur| {
-                log::warn!("Connection failed: {err}, retrying in {dur:?}");
-            })
-            .call()?;
-
+        url.set_scheme(scheme).unwrap();
         Ok(Self {
-            ws,
-            poller,
-            token: token.to_owned(),
-            http_client,
-            http_url,
-            app_imgs,
-            xdg_dirs,
-            last_msg_id,
+            agent: http_agent(),
+            url,
+            headers,
         })
     }
 
-    /// Build request with auth header, send it, check status code, and return response
-    #[expect(clippy::needless_pass_by_value)]
-    fn send_request(&self, method: ureq::http::Method, url: &url::Url) -> anyhow::Result<Vec<u8>> {
-        log::debug!("{method} {url}");
+    /// Build endpoint URL from path segments
+    fn endpoint(&self, segments: &[&str]) -> anyhow::Result<url::Url> {
+        let mut url = self.url.clone();
+        url.path_segments_mut()
+            .map_err(|()| anyhow::anyhow!("Invalid URL {}", self.url))?
+            .extend(segments);
+        Ok(url)
+    }
+
+    /// Build request with auth headers
+    fn build_request(
+        &self,
+        method: Method,
+        url: &url::Url,
+    ) -> ureq::RequestBuilder<ureq::typestate::WithoutBody> {
+        log::debug!("{method:?} {url}");
         let request = match method {
-            ureq::http::Method::GET => self.http_client.get(url.as_str()),
-            ureq::http::Method::DELETE => self.http_client.delete(url.as_str()),
-            _ => unimplemented!(),
+            Method::Get => self.agent.get(url.as_str()),
+            Method::Delete => self.agent.delete(url.as_str()),
         };
-        let response = request.header("X-Gotify-Key", &self.token).call()?;
+        self.headers.iter().fold(request, |request, (name, value)| {
+            request.header(name, value)
+        })
+    }
+
+    /// Build request with auth headers, send it, check status code, and return response
+    fn send_request(&self, method: Method, url: &url::Url) -> anyhow::Result<Vec<u8>> {
+        let response = self.build_request(method, url).call()?;
         anyhow::ensure!(
             response.status().is_success(),
             "HTTP response: {}",
@@ -186,7 +365,7 @@ impl Client {
     /// Add auth header to request, send it, check status code, and parse JSON response
     fn send_api_request<T: serde::de::DeserializeOwned>(
         &self,
-        method: ureq::http::Method,
+        method: Method,
         url: &url::Url,
     ) -> anyhow::Result<T> {
         let json_data = String::from_utf8(self.send_request(method, url)?)?;
@@ -194,17 +373,185 @@ impl Client {
         Ok(serde_json::from_str(&json_data)?)
     }
 
+    /// Fetch all applications
+    pub(crate) fn applications(&self) -> anyhow::Result<Vec<apps::App>> {
+        let apps: Vec<AppInfo> =
+            self.send_api_request(Method::Get, &self.endpoint(&["application"])?)?;
+        log::debug!("Got {} application(s)", apps.len());
+        Ok(apps
+            .into_iter()
+            .map(|a| apps::App {
+                id: a.id,
+                name: a.name,
+                description: a.description,
+                image_url: Some(a.image).filter(|i| !i.is_empty()),
+                icon_filepath: None,
+                icon_failure: None,
+            })
+            .collect())
+    }
+
+    /// Download file from server, for example app image
+    pub(crate) fn download(&self, rel_url: &str) -> anyhow::Result<Vec<u8>> {
+        self.send_request(Method::Get, &self.endpoint(&[rel_url])?)
+    }
+
+    /// Check server health, return a description of the problem if it is unhealthy
+    fn check_health(&self) -> anyhow::Result<Option<String>> {
+        let url = self.endpoint(&["health"])?;
+        // Unhealthy server answers with an error status code, but still sends details
+        let response = self
+            .build_request(Method::Get, &url)
+            .config()
+            .http_status_as_error(false)
+            .timeout_global(Some(HEALTH_TIMEOUT))
+            .build()
+            .call()?;
+        if response.status() == ureq::http::StatusCode::NOT_FOUND {
+            log::debug!("Server has no health endpoint");
+            return Ok(None);
+        }
+        let health: Health = serde_json::from_slice(&response.into_body().read_to_vec()?)?;
+        log::debug!("{health:?}");
+        Ok(
+            (health.health != HEALTH_OK || health.database != HEALTH_OK).then(|| {
+                format!(
+                    "Server health is {:?}, database health is {:?}",
+                    health.health, health.database
+                )
+            }),
+        )
+    }
+
+    /// Log server version, and warn if it lacks features we use
+    fn log_version(&self) -> anyhow::Result<()> {
+        let version: VersionInfo =
+            self.send_api_request(Method::Get, &self.endpoint(&["version"])?)?;
+        log::info!(
+            "Server version {} (commit {}, built {})",
+            version.version,
+            version.commit,
+            version.build_date
+        );
+        match parse_version(&version.version) {
+            Some(v) if v < MIN_SERVER_VERSION => log::warn!(
+                "Server version {} is older than {}, some features may not work",
+                version.version,
+                MIN_SERVER_VERSION.map(|n| n.to_string()).join(".")
+            ),
+            Some(_) => {}
+            None => log::warn!("Unable to parse server version {:?}", version.version),
+        }
+        Ok(())
+    }
+}
+
+/// Parse server version string like "2.4.0" or "v2.4.0-rc1"
+fn parse_version(version: &str) -> Option<[u64; 3]> {
+    let version = version.trim_start_matches('v');
+    let mut parts = version
+        .split_once('-')
+        .map_or(version, |(v, _)| v)
+        .split('.')
+        .map(str::parse);
+    let parsed = [
+        parts.next()?.ok()?,
+        parts.next().unwrap_or(Ok(0)).ok()?,
+        parts.next().unwrap_or(Ok(0)).ok()?,
+    ];
+    parts.next().is_none().then_some(parsed)
+}
+
+impl Client {
+    /// Get a connected Gotify client
+    pub(crate) fn connect(
+        cfg: &config::GotifyConfig,
+        headers: &HeaderMap,
+        last_msg_id: Rc<RefCell<Option<i64>>>,
+        apps: Rc<RefCell<apps::Registry>>,
+        notifier: &mut dyn backend::Notifier,
+    ) -> anyhow::Result<Self> {
+        // Init app img cache
+        let binary_name = env!("CARGO_PKG_NAME");
+        let xdg_dirs = xdg::BaseDirectories::with_prefix(binary_name);
+
+        // HTTP client (non WS)
+        let api = Api::new(&cfg.url, headers.to_owned())?;
+
+        // Connect gotify client, with retries
+        let mut unhealthy_notified = false;
+        let (ws, poller) = (|| {
+            // Check server health first to report a meaningful error,
+            // but connect anyway, the health endpoint may be unreachable or behind a proxy
+            match api.check_health() {
+                Ok(Some(problem)) => {
+                    log::warn!("{problem}");
+                    if !unhealthy_notified {
+                        unhealthy_notified = true;
+                        if let Err(err) =
+                            notif::show_status(notifier, "Gotify server is unhealthy", &problem)
+                        {
+                            log::warn!("Failed to show notification: {err:?}");
+                        }
+                    }
+                }
+                Ok(None) => {}
+                Err(err) => log::warn!("Failed to check server health: {err:?}"),
+            }
+            Self::try_connect(&cfg.url, headers)
+        })
+        .retry(
+            backon::ExponentialBuilder::default()
+                .with_factor(1.5)
+                .with_min_delay(Duration::from_millis(250))
+                .with_max_delay(Duration::from_secs(60))
+                .without_max_times(),
+        )
+        .notify(|err, dur| {
+            log::warn!("Connection failed: {err}, retrying in {dur:?}");
+        })
+        .call()?;
+
+        if let Err(err) = api.log_version() {
+            log::warn!("Failed to get server version: {err:?}");
+        }
+
+        let waker = Arc::new(mio::Waker::new(poller.registry(), WAKE_TOKEN)?);
+        let downloader = icons::Downloader::new(api.clone())?;
+        let client = Self {
+            ws,
+            poller,
+            waker,
+            api,
+            apps,
+            downloader,
+            xdg_dirs,
+            last_msg_id,
+            last_rx: Instant::now(),
+            ping_tx: None,
+        };
+
+        if client.apps.borrow().is_stale()
+            && let Err(err) = client.refresh_apps()
+        {
+            log::warn!("Failed to get applications: {err:?}");
+        }
+        client.prefetch_app_imgs();
+
+        Ok(client)
+    }
+
     /// Connect gotify client
-    fn try_connect(url: &url::Url, token: &str) -> anyhow::Result<(WebSocket, mio::Poll)> {
+    fn try_connect(url: &url::Url, headers: &HeaderMap) -> anyhow::Result<(WebSocket, mio::Poll)> {
         // WS connect & handshake
         let mut url = url.to_owned();
         url.path_segments_mut()
             .map_err(|()| anyhow::anyhow!("Invalid URL"))?
             .push("stream");
         let mut request = url.into_client_request()?;
-        let headers = request.headers_mut();
-        headers.insert("User-Agent", HeaderValue::from_str(&USER_AGENT)?);
-        headers.insert("X-Gotify-Key", HeaderValue::from_str(token)?);
+        let request_headers = request.headers_mut();
+        request_headers.insert("User-Agent", HeaderValue::from_str(&USER_AGENT)?);
+        request_headers.extend(headers.clone());
         let (mut ws, response) = tungstenite::connect(request)?;
 
         // Check response
@@ -223,12 +570,15 @@ impl Client {
         let poller_registry = poller.registry();
         let fd = match ws.get_ref() {
             tungstenite::stream::MaybeTlsStream::Plain(s) => s.as_raw_fd(),
+            #[cfg(feature = "native-tls")]
             tungstenite::stream::MaybeTlsStream::NativeTls(t) => t.get_ref().as_raw_fd(),
-            _ => unimplemented!(),
+            #[cfg(any(feature = "rustls-webpki-roots", feature = "rustls-platform-roots"))]
+            tungstenite::stream::MaybeTlsStream::Rustls(t) => t.get_ref().as_raw_fd(),
+            _ => anyhow::bail!("Unsupported websocket stream type"),
         };
         poller_registry.register(
             &mut mio::unix::SourceFd(&fd),
-            mio::Token(0),
+            WS_TOKEN,
             mio::Interest::READABLE,
         )?;
 
@@ -237,30 +587,26 @@ impl Client {
 
     /// Catch up missed messages since the last received one
     pub(crate) fn get_missed_messages(&mut self) -> anyhow::Result<Vec<Message>> {
-        let mut missed_messages: Vec<Message> = if let Some(last_msg_id) =
-            *self.last_msg_id.borrow()
-        {
-            // Get all recent messages
-            let mut url = self.http_url.clone();
-            url.path_segments_mut()
-                .map_err(|()| anyhow::anyhow!("Invalid URL {}", self.http_url))?
-                .push("message");
-            url.query_pairs_mut().append_pair("limit", "200");
-            let all_messages: AllMessages = self.send_api_request(ureq::http::Method::GET, &url)?;
-
-            // Keep the ones we have not yet seen
-            all_messages
-                .messages
-                .into_iter()
-                .filter(|m| m.id > last_msg_id)
-                .rev()
-                .collect()
-        } else {
-            vec![]
-        };
+        let mut missed_messages: Vec<Message> =
+            if let Some(last_msg_id) = *self.last_msg_id.borrow() {
+                // Get all recent messages
+                let mut url = self.api.endpoint(&["message"])?;
+                url.query_pairs_mut().append_pair("limit", "200");
+                let all_messages: AllMessages = self.api.send_api_request(Method::Get, &url)?;
+
+                // Keep the ones we have not yet seen
+                all_messages
+                    .messages
+                    .into_iter()
+                    .filter(|m| m.id > last_msg_id)
+                    .rev()
+                    .collect()
+            } else {
+                vec![]
+            };
 
         for missed_message in &mut missed_messages {
-            self.set_message_app_img(missed_message)?;
+            self.try_set_message_app(missed_message);
         }
 
         if let Some(last_msg) = missed_messages.iter().last() {
@@ -270,14 +616,37 @@ impl Client {
         Ok(missed_messages)
     }
 
-    /// Get pending gotify messages
-    pub(crate) fn get_message(&mut self) -> anyhow::Result<Message> {
+    /// Get waker to interrupt waiting for a message
+    pub(crate) fn waker(&self) -> Arc<mio::Waker> {
+        Arc::clone(&self.waker)
+    }
+
+    /// Get web UI URL showing the messages of an app
+    pub(crate) fn web_url(&self, app_id: i64) -> url::Url {
+        let mut url = self.api.url.clone();
+        url.set_fragment(Some(&format!("/messages/{app_id}")));
+        url
+    }
+
+    /// Wait for next gotify message, return `None` if none was received before timeout,
+    /// or if woken up
+    pub(crate) fn get_message(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> anyhow::Result<Option<Message>> {
+        let deadline = timeout.map(|t| Instant::now() + t);
         loop {
             // Poll to detect stale socket, so we can trigger reconnect,
             // this can occur when returning from sleep/hibernation
             // Without this, read_message blocks forever even if server already closed its end
-            let mut poller_events = mio::Events::with_capacity(1);
-            let poll_res = self.poller.poll(&mut poller_events, None);
+            let mut poller_events = mio::Events::with_capacity(2);
+            let poll_timeout = match deadline {
+                Some(deadline) => self
+                    .poll_timeout()
+                    .min(deadline.saturating_duration_since(Instant::now())),
+                None => self.poll_timeout(),
+            };
+            let poll_res = self.poller.poll(&mut poller_events, Some(poll_timeout));
             match poll_res {
                 Err(e) if e.kind() == ErrorKind::Interrupted => {
                     return Err(NeedsReconnect::Io(e).into());
@@ -286,9 +655,17 @@ impl Client {
                 _ => {}
             }
             if poller_events.is_empty() {
+                self.check_liveness()?;
+                if deadline.is_some_and(|d| d <= Instant::now()) {
+                    return Ok(None);
+                }
                 continue;
             }
             log::trace!("Event: {poller_events:?}");
+            if !poller_events.iter().any(|e| e.token() == WS_TOKEN) {
+                // Woken up, let the caller process its events
+                return Ok(None);
+            }
 
             // Read message
             let read_res = self.ws.read();
@@ -299,18 +676,47 @@ impl Client {
                 {
                     return Err(NeedsReconnect::Protocol(e).into());
                 }
+                Err(tungstenite::Error::Io(e)) => {
+                    return Err(NeedsReconnect::Io(e).into());
+                }
+                Err(
+                    e @ (tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed),
+                ) => {
+                    return Err(NeedsReconnect::Closed(e.to_string()).into());
+                }
                 Err(_) => read_res?,
             };
             log::trace!("Got message: {ws_msg:?}");
+            self.last_rx = Instant::now();
+            self.ping_tx = None;
 
             // Check message type
             let msg_str = match ws_msg {
                 tungstenite::protocol::Message::Text(msg_str) => msg_str,
                 tungstenite::protocol::Message::Ping(_) => {
+                    // Pong reply has been queued by tungstenite
                     self.ws.flush()?;
                     continue;
                 }
-                _ => anyhow::bail!("Unexpected message type: {ws_msg:?}"),
+                tungstenite::protocol::Message::Pong(_) => {
+                    log::trace!("Server is alive");
+                    continue;
+                }
+                tungstenite::protocol::Message::Close(close_frame) => {
+                    // Close reply has been queued by tungstenite, send it to complete the handshake
+                    if let Err(e) = self.ws.flush() {
+                        log::debug!("Failed to reply to close frame: {e}");
+                    }
+                    return Err(NeedsReconnect::Closed(
+                        close_frame.map_or_else(|| "no reason".to_owned(), |f| f.to_string()),
+                    )
+                    .into());
+                }
+                tungstenite::protocol::Message::Binary(_)
+                | tungstenite::protocol::Message::Frame(_) => {
+                    log::warn!("Ignoring unexpected message: {ws_msg:?}");
+                    continue;
+                }
             };
 
             // Parse
@@ -318,119 +724,218 @@ impl Client {
             let mut msg: Message = serde_json::from_str(&msg_str)?;
 
             // Get app image
-            self.set_message_app_img(&mut msg)?;
+            self.try_set_message_app(&mut msg);
 
             self.last_msg_id.replace(Some(msg.id));
-            return Ok(msg);
+            return Ok(Some(msg));
+        }
+    }
+
+    /// Get maximum duration to wait for socket events before checking liveness
+    fn poll_timeout(&self) -> Duration {
+        match self.ping_tx {
+            Some(ping_tx) => PONG_TIMEOUT.saturating_sub(ping_tx.elapsed()),
+            None => PING_INTERVAL.saturating_sub(self.last_rx.elapsed()),
+        }
+    }
+
+    /// Ping server if it has been silent for too long, or fail if it did not answer a previous ping
+    fn check_liveness(&mut self) -> anyhow::Result<()> {
+        match self.ping_tx {
+            Some(ping_tx) if ping_tx.elapsed() >= PONG_TIMEOUT => {
+                Err(NeedsReconnect::PongTimeout(ping_tx.elapsed()).into())
+            }
+            None if self.last_rx.elapsed() >= PING_INTERVAL => {
+                log::debug!(
+                    "No data received for {:?}, sending ping",
+                    self.last_rx.elapsed()
+                );
+                match self.ws.send(tungstenite::protocol::Message::Ping(
+                    tungstenite::Bytes::new(),
+                )) {
+                    Ok(()) => {}
+                    Err(tungstenite::Error::Io(e)) => return Err(NeedsReconnect::Io(e).into()),
+                    Err(e) => return Err(e.into()),
+                }
+                self.ping_tx = Some(Instant::now());
+                Ok(())
+            }
+            _ => Ok(()),
         }
     }
 
     /// Delete gotify message
     pub(crate) fn delete_message(&mut self, msg_id: i64) -> anyhow::Result<()> {
-        let mut url = self.http_url.clone();
-        url.path_segments_mut()
-            .map_err(|()| anyhow::anyhow!("Invalid URL {}", self.http_url))?
-            .push("message")
-            .push(&format!("{msg_id}"));
-        let _ = self.send_request(ureq::http::Method::DELETE, &url)?;
+        let url = self.api.endpoint(&["message", &msg_id.to_string()])?;
+        let _ = self.api.send_request(Method::Delete, &url)?;
         Ok(())
     }
 
-    /// Download (or get from cache) and set app image for a message
-    fn set_message_app_img(&mut self, msg: &mut Message) -> anyhow::Result<()> {
-        msg.app_img_filepath = match self.app_imgs.get(&msg.appid) {
-            // Cache hit, has file
-            Some(Some(cache_hit_img_filepath)) => {
-                if cache_hit_img_filepath.is_file() {
-                    // Image file already exists
-                    Some(cache_hit_img_filepath.to_owned())
-                } else {
-                    log::warn!(
-                        "File {cache_hit_img_filepath:?} has been removed, will try to download it again"
-                    );
-
-                    // Download image file if app has one
-                    self.download_app_img(msg.appid, None, cache_hit_img_filepath)?
-                        .then(|| cache_hit_img_filepath.to_owned())
+    /// Set app name and icon for a message if possible, a failure only means no icon
+    pub(crate) fn try_set_message_app(&self, msg: &mut Message) {
+        if let Err(err) = self.set_message_app(msg) {
+            log::warn!("Failed to get app image for message {}: {err:?}", msg.id);
+        }
+    }
+
+    /// Set app name and icon for a message, from local configuration or server.
+    /// App image is taken from cache, or its download is queued
+    fn set_message_app(&self, msg: &mut Message) -> anyhow::Result<()> {
+        self.process_downloads();
+
+        // Icon configured locally by app id does not need anything from the server
+        let has_config_icon = self.has_config_icon(msg.appid);
+        let is_known_app = self.apps.borrow().get(msg.appid).is_some();
+        if !is_known_app && !has_config_icon {
+            // Cache miss, app may have been created after our last fetch
+            if self.apps.borrow().can_refresh() {
+                log::info!("Unknown app id {}, fetching applications", msg.appid);
+                if let Err(err) = self.refresh_apps() {
+                    log::warn!("Failed to get applications: {err:?}");
                 }
+                self.prefetch_app_imgs();
+            } else {
+                log::debug!("Unknown app id {}", msg.appid);
             }
+        }
 
-            // Cache hit, has no file
-            Some(None) => None,
-
-            // Cache miss
-            None => {
-                // Create cache path
-                let new_entry = if let Some(image_rel_url) = self.app_img_url(msg.appid)? {
-                    let cache_filename = Path::new(&image_rel_url)
-                        .file_name()
-                        .ok_or_else(|| anyhow::anyhow!("Invalid image URL"))?;
-                    let img_filepath = self.xdg_dirs.place_cache_file(cache_filename)?;
-
-                    if img_filepath.is_file() {
-                        // Image file already exists
-                        Some(img_filepath)
-                    } else {
-                        // Download image file if app has one
-                        self.download_app_img(msg.appid, Some(image_rel_url), &img_filepath)?
-                            .then_some(img_filepath)
-                    }
-                } else {
-                    None
-                };
-                self.app_imgs.insert(msg.appid, new_entry.clone());
-                new_entry
+        {
+            let apps = self.apps.borrow();
+            let app_config = apps.config(msg.appid);
+            msg.app_name = app_config
+                .and_then(|c| c.display_name.clone())
+                .or_else(|| apps.get(msg.appid).map(|a| a.name.clone()));
+            msg.app_sound = app_config.and_then(|c| c.sound.clone());
+            msg.app_summary = app_config.and_then(|c| c.summary.clone());
+            msg.app_body = app_config.and_then(|c| c.body.clone());
+            msg.app_markup = app_config.is_some_and(|c| c.markup);
+            if let Some(icon) = app_config.and_then(|c| c.icon.as_deref()) {
+                msg.app_icon = Some(Icon::from(icon));
+                return Ok(());
             }
-        };
+        }
+
+        if let Some((image_rel_url, img_filepath)) = self.app_img_filepath(msg.appid)? {
+            if img_filepath.is_file() {
+                msg.app_icon = Some(Icon::File(img_filepath));
+            } else {
+                // Don't wait for it, next message from this app will have it
+                log::debug!("Image for app {} is not available yet", msg.appid);
+                self.queue_app_img(msg.appid, &image_rel_url, &img_filepath);
+            }
+        }
+        self.save_apps();
 
         Ok(())
     }
 
-    /// Get app image relative URL from server
-    fn app_img_url(&self, app_id: i64) -> anyhow::Result<Option<String>> {
-        // Get app info
-        let mut url = self.http_url.clone();
-        url.path_segments_mut()
-            .map_err(|()| anyhow::anyhow!("Invalid URL {}", self.http_url))?
-            .push("application");
-        let apps: Vec<AppInfo> = self.send_api_request(ureq::http::Method::GET, &url)?;
+    /// Is the icon of an app configured locally?
+    fn has_config_icon(&self, app_id: i64) -> bool {
+        self.apps
+            .borrow()
+            .config(app_id)
+            .is_some_and(|c| c.icon.is_some())
+    }
 
-        // Parse it
-        let matching_app = apps.into_iter().find(|a| a.id == app_id);
+    /// Queue app image download, unless previous failure is too recent
+    fn queue_app_img(&self, app_id: i64, image_rel_url: &str, img_filepath: &Path) {
+        if self
+            .apps
+            .borrow()
+            .get(app_id)
+            .is_some_and(apps::App::can_download_icon)
+        {
+            self.downloader.queue(app_id, image_rel_url, img_filepath);
+        } else {
+            log::debug!("Not retrying image download for app {app_id} yet");
+        }
+    }
 
-        Ok(matching_app.map(|a| a.image).filter(|i| !i.is_empty()))
+    /// Record results of completed app image downloads
+    fn process_downloads(&self) {
+        let results = self.downloader.completed();
+        if results.is_empty() {
+            return;
+        }
+        let mut apps = self.apps.borrow_mut();
+        for (app_id, success) in results {
+            apps.set_icon_result(app_id, success);
+        }
     }
 
-    /// Download Gotify app image if any, return true if we have downloaded one
-    fn download_app_img(
-        &self,
-        app_id: i64,
-        image_rel_url: Option<String>,
-        img_filepath: &Path,
-    ) -> anyhow::Result<bool> {
-        if let Some(image_rel_url) = image_rel_url.map_or_else(
-            || -> anyhow::Result<_> { self.app_img_url(app_id) },
-            |v| Ok(Some(v)),
-        )? {
-            let mut img_url = self.http_url.clone();
-            img_url
-                .path_segments_mut()
-                .map_err(|()| {
-                    anyhow::anyhow!(
-                        "Failed to build image URL from base {:?} and path {:?}",
-                        self.http_url.as_str(),
-                        image_rel_url
-                    )
-                })?
-                .push(&image_rel_url);
-            let img_data = self.send_request(ureq::http::Method::GET, &img_url)?;
-            fs::write(img_filepath, &img_data)?;
-            log::debug!("{img_filepath:?} written");
-            Ok(true)
-        } else {
-            Ok(false)
+    /// Get image relative URL and local cache filepath for an app, if it has an image
+    fn app_img_filepath(&self, app_id: i64) -> anyhow::Result<Option<(String, PathBuf)>> {
+        let mut apps = self.apps.borrow_mut();
+        let Some(app) = apps.get(app_id) else {
+            return Ok(None);
+        };
+        let Some(image_rel_url) = app.image_url.clone() else {
+            return Ok(None);
+        };
+
+        // Create cache path, images are converted to PNG and scaled down when downloaded.
+        // Size is in the name, so that files cached in another format are not reused
+        let mut cache_filename = Path::new(&image_rel_url)
+            .file_stem()
+            .ok_or_else(|| anyhow::anyhow!("Invalid image URL {image_rel_url:?}"))?
+            .to_owned();
+        cache_filename.push(format!("-{}.png", icons::ICON_SIZE));
+        let img_filepath = match &app.icon_filepath {
+            Some(img_filepath) if img_filepath.file_name() == Some(&cache_filename) => {
+                img_filepath.to_owned()
+            }
+            _ => self.xdg_dirs.place_cache_file(cache_filename)?,
+        };
+        apps.set_icon_filepath(app_id, Some(img_filepath.clone()));
+        Ok(Some((image_rel_url, img_filepath)))
+    }
+
+    /// Queue download of all missing app images, and remove those no longer used
+    fn prefetch_app_imgs(&self) {
+        self.process_downloads();
+        let app_ids: Vec<i64> = self.apps.borrow().iter().map(|a| a.id).collect();
+        for app_id in app_ids {
+            if self.has_config_icon(app_id) {
+                continue;
+            }
+            match self.app_img_filepath(app_id) {
+                Ok(Some((image_rel_url, img_filepath))) if !img_filepath.is_file() => {
+                    self.queue_app_img(app_id, &image_rel_url, &img_filepath);
+                }
+                Ok(_) => {}
+                Err(err) => log::warn!("Unable to get image for app {app_id}: {err:?}"),
+            }
+        }
+        self.save_apps();
+        // All icon paths are assigned now
+        if let Err(err) = self.apps.borrow().prune() {
+            log::warn!("Failed to remove orphan cache files: {err:?}");
         }
     }
+
+    /// Persist app registry if it has changed
+    fn save_apps(&self) {
+        if let Err(err) = self.apps.borrow_mut().save() {
+            log::warn!("Failed to save application cache: {err:?}");
+        }
+    }
+
+    /// Fetch all applications from server in a single call, and update registry
+    fn refresh_apps(&self) -> anyhow::Result<()> {
+        let apps = match self.api.applications() {
+            Ok(apps) => apps,
+            Err(err) => {
+                self.apps.borrow_mut().set_fetch_failed();
+                self.save_apps();
+                return Err(err);
+            }
+        };
+
+        let mut registry = self.apps.borrow_mut();
+        registry.update(apps);
+        registry.save()?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -442,6 +947,49 @@ mod tests {
     /// Marker env var for the re-executed child of `http_agent_ignores_env_proxy`.
     const PROXY_CHILD_MARKER: &str = "GOTIFY_DESKTOP_TEST_PROXY_CHILD";
 
+    #[test]
+    fn parse_message_missing_fields() {
+        let msg: Message =
+            serde_json::from_str(r#"{"id": 12, "appid": 3, "message": "text"}"#).unwrap();
+        assert_eq!(msg.id, 12);
+        assert_eq!(msg.text, "text");
+        assert_eq!(msg.title, "");
+        assert_eq!(msg.priority, 0);
+        assert_eq!(msg.date, "");
+    }
+
+    #[test]
+    fn build_request_headers() {
+        let cfg: config::GotifyConfig = toml::from_str(
+            r#"
+url = "wss://example.com"
+token = "abcdef1234"
+headers = { CF-Access-Client-Id = { command = "echo id1234" } }
+basic_auth = { username = "Aladdin", password = "open sesame" }
+"#,
+        )
+        .unwrap();
+        let headers = request_headers(&cfg).unwrap();
+        assert_eq!(headers.len(), 3);
+        assert_eq!(headers["X-Gotify-Key"], "abcdef1234");
+        assert_eq!(headers["CF-Access-Client-Id"], "id1234");
+        assert_eq!(
+            headers[header::AUTHORIZATION],
+            "Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ=="
+        );
+        assert!(headers.values().all(HeaderValue::is_sensitive));
+    }
+
+    #[test]
+    fn parse_server_version() {
+        assert_eq!(parse_version("2.4.0"), Some([2, 4, 0]));
+        assert_eq!(parse_version("v1.2"), Some([1, 2, 0]));
+        assert_eq!(parse_version("2.6.1-rc1"), Some([2, 6, 1]));
+        assert_eq!(parse_version("unknown"), None);
+        assert_eq!(parse_version("1.2.3.4"), None);
+        assert!(parse_version("1.9.9").unwrap() < MIN_SERVER_VERSION);
+    }
+
     #[test]
     fn http_agent_ignores_env_proxy() {
         // A proxy env var is set only in a child process, never mutated in this one.
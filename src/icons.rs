@@ -0,0 +1,156 @@
+//! Background application icon downloads
+
+use std::{
+    collections::HashSet,
+    fs,
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError, mpsc},
+    thread,
+};
+
+use crate::gotify;
+
+/// Maximum icon width & height, larger images are scaled down.
+/// Only this size is cached, notification servers scale icons as needed
+pub(crate) const ICON_SIZE: u32 = 128;
+
+/// Icon download request
+struct Job {
+    /// App id
+    app_id: i64,
+    /// Image URL relative to the server base URL
+    image_rel_url: String,
+    /// Local cache filepath to write to
+    filepath: PathBuf,
+}
+
+/// Downloads icons in a background thread, so that message reception never waits for them
+pub(crate) struct Downloader {
+    /// Job channel to the download thread
+    tx: mpsc::Sender<Job>,
+    /// Download results channel from the download thread, with app id and success
+    results: mpsc::Receiver<(i64, bool)>,
+    /// Filepaths of icons queued or being downloaded
+    pending: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+impl Downloader {
+    /// Start download thread, it stops when the downloader is dropped
+    pub(crate) fn new(api: gotify::Api) -> anyhow::Result<Self> {
+        let (tx, rx) = mpsc::channel();
+        let (results_tx, results) = mpsc::channel();
+        let pending = Arc::new(Mutex::new(HashSet::new()));
+        let thread_pending = Arc::clone(&pending);
+        thread::Builder::new()
+            .name("icon-downloader".to_owned())
+            .spawn(move || run(&api, &rx, &results_tx, &thread_pending))?;
+        Ok(Self {
+            tx,
+            results,
+            pending,
+        })
+    }
+
+    /// Queue icon download, unless it is already pending
+    pub(crate) fn queue(&self, app_id: i64, image_rel_url: &str, filepath: &Path) {
+        if !self
+            .pending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(filepath.to_owned())
+        {
+            return;
+        }
+        log::debug!("Queuing download of {image_rel_url:?} to {filepath:?}");
+        let job = Job {
+            app_id,
+            image_rel_url: image_rel_url.to_owned(),
+            filepath: filepath.to_owned(),
+        };
+        if self.tx.send(job).is_err() {
+            log::warn!("Icon download thread is gone");
+        }
+    }
+
+    /// Get results of downloads completed since last call, with app id and success
+    pub(crate) fn completed(&self) -> Vec<(i64, bool)> {
+        self.results.try_iter().collect()
+    }
+}
+
+/// Download thread loop
+fn run(
+    api: &gotify::Api,
+    rx: &mpsc::Receiver<Job>,
+    results_tx: &mpsc::Sender<(i64, bool)>,
+    pending: &Mutex<HashSet<PathBuf>>,
+) {
+    for job in rx {
+        let success = if job.filepath.is_file() {
+            true
+        } else if let Err(err) = download(api, &job) {
+            log::warn!(
+                "Failed to download icon {:?} to {:?}: {err:?}",
+                job.image_rel_url,
+                job.filepath
+            );
+            false
+        } else {
+            true
+        };
+        // Receiver is gone if client has been dropped, nothing to do
+        let _ = results_tx.send((job.app_id, success));
+        pending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&job.filepath);
+    }
+}
+
+/// Convert arbitrary image to PNG icon, scaled down if needed
+fn to_png_icon(img_data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut img = image::load_from_memory(img_data)?;
+    if img.width() > ICON_SIZE || img.height() > ICON_SIZE {
+        img = img.resize(ICON_SIZE, ICON_SIZE, image::imageops::FilterType::Lanczos3);
+    }
+    let mut png_data = Vec::new();
+    img.write_to(&mut Cursor::new(&mut png_data), image::ImageFormat::Png)?;
+    Ok(png_data)
+}
+
+/// Download icon, convert it, and atomically write it, so that a partial file is never visible
+fn download(api: &gotify::Api, job: &Job) -> anyhow::Result<()> {
+    let img_data = to_png_icon(&api.download(&job.image_rel_url)?)?;
+    let mut tmp_filepath = job.filepath.clone().into_os_string();
+    tmp_filepath.push(".tmp");
+    if let Err(err) = fs::write(&tmp_filepath, &img_data) {
+        let _ = fs::remove_file(&tmp_filepath);
+        return Err(err.into());
+    }
+    fs::rename(&tmp_filepath, &job.filepath)?;
+    log::debug!("{:?} written", job.filepath);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert_icon() {
+        let mut jpeg_data = Vec::new();
+        image::DynamicImage::new_rgb8(512, 256)
+            .write_to(&mut Cursor::new(&mut jpeg_data), image::ImageFormat::Jpeg)
+            .unwrap();
+        let png_data = to_png_icon(&jpeg_data).unwrap();
+        assert_eq!(
+            image::guess_format(&png_data).unwrap(),
+            image::ImageFormat::Png
+        );
+        let img = image::load_from_memory(&png_data).unwrap();
+        assert_eq!((img.width(), img.height()), (ICON_SIZE, ICON_SIZE / 2));
+
+        assert!(to_png_icon(b"not an image").is_err());
+    }
+}
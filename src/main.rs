@@ -1,25 +1,76 @@
 //! Gotify desktop daemon
 
-use std::{cell::RefCell, process::Command, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    env,
+    process::Command,
+    rc::Rc,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
 
 use anyhow::Context as _;
 
+mod actions;
+mod alerts;
+mod apps;
+mod backend;
 mod config;
+mod control;
+mod dedupe;
 mod gotify;
+mod icons;
 mod notif;
+mod ratelimit;
+mod retry;
+mod snooze;
+mod template;
 
-/// Run configured command on message reception
-fn run_on_msg_command(
+/// Maximum number of message handling steps waiting for retry
+const RETRY_QUEUE_CAPACITY: usize = 100;
+
+/// Maximum number of attempts for a message handling step
+const RETRY_MAX_ATTEMPTS: u32 = 5;
+
+/// Number of recently handled messages kept, that can be snoozed by id
+const RECENT_CAPACITY: usize = 100;
+
+/// Delay before reconnecting if we failed to catch up missed messages
+const CATCH_UP_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Message handling step that may fail and be retried
+#[derive(Clone, Copy, Debug)]
+enum Step {
+    /// Show desktop notification
+    Notify,
+    /// Delete message on server
+    Delete,
+}
+
+/// Queue of message handling steps to retry
+type RetryQueue = retry::Queue<(gotify::Message, Step)>;
+
+/// Split configured command into program and arguments
+fn parse_command(cmd: Option<String>) -> anyhow::Result<Option<(String, Vec<String>)>> {
+    cmd.map(|cmd| {
+        shlex::split(&cmd)
+            .with_context(|| format!("Failed to split command arguments for {cmd:?}"))?
+            .split_first()
+            .map(|t| (t.0.to_owned(), t.1.to_owned()))
+            .ok_or_else(|| anyhow::anyhow!("Empty command"))
+    })
+    .transpose()
+}
+
+/// Run configured command for a message
+fn run_msg_command(
     message: &gotify::Message,
-    on_msg_command: &(String, Vec<String>),
+    command: &(String, Vec<String>),
 ) -> anyhow::Result<()> {
-    log::info!(
-        "Running on message command: {} {}",
-        on_msg_command.0,
-        on_msg_command.1.join(" ")
-    );
-    Command::new(&on_msg_command.0)
-        .args(&on_msg_command.1)
+    log::info!("Running command: {} {}", command.0, command.1.join(" "));
+    Command::new(&command.0)
+        .args(&command.1)
         .env("GOTIFY_MSG_PRIORITY", format!("{}", message.priority))
         .env("GOTIFY_MSG_TITLE", &message.title)
         .env("GOTIFY_MSG_TEXT", &message.text)
@@ -29,117 +80,623 @@ fn run_on_msg_command(
     Ok(())
 }
 
-/// Process new message
-fn handle_message(
-    message: &gotify::Message,
-    min_priority: i64,
-    on_msg_command: Option<&(String, Vec<String>)>,
-    delete: bool,
-    client: &mut gotify::Client,
-) -> anyhow::Result<()> {
-    log::info!("Got {message:?}");
-
-    if message.priority >= min_priority {
-        notif::show(message)?;
-    } else {
-        log::debug!(
-            "Ignoring notification for message of priority {}",
-            message.priority
+/// Message handling settings & state, kept across reconnects
+struct Handler {
+    /// Notification configuration
+    notif_cfg: config::NotificationConfig,
+    /// Gotify server host name, for notification templates
+    server_host: String,
+    /// Command to run on message reception
+    on_msg_command: Option<(String, Vec<String>)>,
+    /// Command to run when an alert is not acknowledged in time
+    escalation_command: Option<(String, Vec<String>)>,
+    /// Should messages be deleted on reception?
+    auto_delete: bool,
+    /// Message handling steps that failed and need to be retried
+    retries: RetryQueue,
+    /// Recent notifications, for coalescing
+    notif_groups: notif::Groups,
+    /// Notification backend
+    notifier: Box<dyn backend::Notifier>,
+    /// Notifications waiting for a notification server
+    notif_queue: notif::Queue,
+    /// Notification rate limiter
+    limiter: ratelimit::Limiter,
+    /// Identical messages detection
+    deduper: dedupe::Deduper,
+    /// Notification actions watcher
+    actions: actions::Dispatcher,
+    /// Snoozed messages
+    snoozed: snooze::Queue,
+    /// Recently handled messages with the id of their notification if any, newest last
+    recent: VecDeque<(gotify::Message, Option<u32>)>,
+    /// Alerts waiting to be acknowledged
+    alerts: alerts::Alerts,
+}
+
+impl Handler {
+    /// Process new message
+    fn handle_message(
+        &mut self,
+        message: &gotify::Message,
+        client: &mut gotify::Client,
+    ) -> anyhow::Result<()> {
+        log::info!("Got {message:?}");
+        if self.recent.len() >= RECENT_CAPACITY {
+            self.recent.pop_front();
+        }
+        self.recent.push_back((message.clone(), None));
+
+        let repeats = self.deduper.check(message, Instant::now());
+        if message.priority < self.notif_cfg.min_priority {
+            log::debug!(
+                "Ignoring notification for message of priority {}",
+                message.priority
+            );
+        } else if repeats > 0 {
+            log::info!("Message repeated {repeats} time(s), not showing a new notification");
+            if self.notif_cfg.dedupe.counter {
+                self.show_repeated(message, repeats);
+            }
+        } else if self.alerts.is_alert(message) {
+            // Alerts are never rate limited
+            self.alerts.start(message, Instant::now());
+            self.try_step(Step::Notify, message, 0, client)?;
+        } else if !self.limiter.allow(message, Instant::now()) {
+            log::info!("Rate limit reached, suppressing notification");
+        } else {
+            self.try_step(Step::Notify, message, 0, client)?;
+        }
+
+        if let Some(on_msg_command) = &self.on_msg_command
+            && let Err(e) = run_msg_command(message, on_msg_command)
+        {
+            log::warn!("Command {on_msg_command:?} failed with error: {e:?}");
+        }
+
+        if self.auto_delete {
+            self.try_step(Step::Delete, message, 0, client)?;
+        }
+
+        Ok(())
+    }
+
+    /// Run message handling step, log failure and schedule retry if it is transient,
+    /// only fail for fatal errors
+    fn try_step(
+        &mut self,
+        step: Step,
+        message: &gotify::Message,
+        attempts: u32,
+        client: &mut gotify::Client,
+    ) -> anyhow::Result<()> {
+        if matches!(step, Step::Notify) && !self.notifier.available() {
+            self.queue_notification(message);
+            return Ok(());
+        }
+        let res = match step {
+            Step::Notify => notif::show(
+                message,
+                &self.notif_cfg,
+                &mut self.notif_groups,
+                notif::Options {
+                    update: None,
+                    alert: self.alerts.is_active(message.id),
+                },
+                &self.server_host,
+                self.notifier.as_mut(),
+                &mut self.actions,
+            )
+            .map(|notif_id| {
+                if let Some(notif_id) = notif_id {
+                    self.deduper.set_notif_id(message, notif_id);
+                    self.alerts.set_notif_id(message.id, notif_id);
+                    if let Some((_, recent_notif_id)) = self
+                        .recent
+                        .iter_mut()
+                        .rev()
+                        .find(|(m, _)| m.id == message.id)
+                    {
+                        *recent_notif_id = Some(notif_id);
+                    }
+                }
+            }),
+            Step::Delete => client.delete_message(message.id),
+        };
+        let Err(err) = res else {
+            return Ok(());
+        };
+        // D-Bus activation of the notification server failed, wait for one to appear
+        if matches!(step, Step::Notify)
+            && self.notif_cfg.backend == config::Backend::Freedesktop
+            && !backend::server_running()
+        {
+            log::warn!("Notify failed for message {}: {err:?}", message.id);
+            self.notifier.set_available(false);
+            self.queue_notification(message);
+            return Ok(());
+        }
+        match retry::classify(&err) {
+            retry::ErrorClass::Fatal => {
+                return Err(err.context(format!("{step:?} failed for message {}", message.id)));
+            }
+            retry::ErrorClass::Permanent => {
+                log::error!("{step:?} failed for message {}: {err:?}", message.id);
+            }
+            retry::ErrorClass::Transient => {
+                log::warn!(
+                    "{step:?} failed for message {}: {err:?}, will retry",
+                    message.id
+                );
+                if let Some((dropped_message, dropped_step)) =
+                    self.retries.push((message.clone(), step), attempts + 1)
+                {
+                    log::error!(
+                        "Giving up {dropped_step:?} for message {}",
+                        dropped_message.id
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Update notification of a repeated message with its repetition count, best effort
+    fn show_repeated(&mut self, message: &gotify::Message, count: usize) {
+        let Some(notif_id) = self.deduper.notif_id(message) else {
+            log::debug!("No notification to update for message {}", message.id);
+            return;
+        };
+        let update = notif::Update {
+            notif_id: Some(notif_id),
+            note: format!("repeated {count} times"),
+        };
+        if let Err(err) = notif::show(
+            message,
+            &self.notif_cfg,
+            &mut self.notif_groups,
+            notif::Options {
+                update: Some(&update),
+                alert: self.alerts.is_active(message.id),
+            },
+            &self.server_host,
+            self.notifier.as_mut(),
+            &mut self.actions,
+        ) {
+            log::warn!("Failed to update notification: {err:?}");
+        }
+    }
+
+    /// Run actions invoked from notifications, and control commands
+    fn process_requests(&mut self, client: &mut gotify::Client) -> anyhow::Result<()> {
+        for request in self.actions.requests() {
+            let (message, action) = match request {
+                actions::Request::Action(message, action) => (message, action),
+                actions::Request::ServerChanged(available) => {
+                    self.server_changed(available, client)?;
+                    continue;
+                }
+                actions::Request::Command(command, reply) => {
+                    let res = self.run_command(command);
+                    // Client may have given up waiting
+                    let _ = reply.send(res.map_err(|e| format!("{e:#}")));
+                    continue;
+                }
+            };
+            log::info!("Action {action:?} invoked for message {}", message.id);
+            match action {
+                config::NotificationAction::Delete => {
+                    self.alerts.acknowledge(message.id);
+                    self.try_step(Step::Delete, &message, 0, client)?;
+                }
+                config::NotificationAction::Acknowledge => {
+                    if self.alerts.acknowledge(message.id) {
+                        log::info!("Alert for message {} acknowledged", message.id);
+                    }
+                }
+                config::NotificationAction::Snooze => {
+                    if let Err(err) = self.snoozed.push(*message, self.snooze_duration()) {
+                        log::warn!("Failed to snooze message: {err:?}");
+                    }
+                }
+                config::NotificationAction::Copy => {
+                    if let Err(err) = actions::copy_text(
+                        &message.text,
+                        self.notif_cfg.clipboard_command.as_deref(),
+                    ) {
+                        log::warn!("Failed to copy message text: {err:?}");
+                    }
+                }
+                config::NotificationAction::Open => {
+                    if let Err(err) = actions::open_url(&client.web_url(message.appid)) {
+                        log::warn!("Failed to open web UI: {err:?}");
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Run control command
+    fn run_command(&mut self, command: control::Command) -> anyhow::Result<()> {
+        match command {
+            control::Command::Snooze { msg_id, duration } => self.snooze_recent(msg_id, duration),
+            control::Command::Ack { msg_id } => {
+                anyhow::ensure!(
+                    self.alerts.acknowledge(msg_id),
+                    "Message {msg_id} has no pending alert"
+                );
+                log::info!("Alert for message {msg_id} acknowledged");
+                Ok(())
+            }
+        }
+    }
+
+    /// Watch XDG notification server availability, if it is the notification backend
+    fn watch_notif_server(&mut self) {
+        // Without a watch, we can not know when a notification server appears, so always try to
+        // show notifications, and rely on retries
+        #[cfg(all(unix, not(target_os = "macos")))]
+        if self.notif_cfg.backend == config::Backend::Freedesktop {
+            match self.actions.watch_server() {
+                Ok(()) => self.notifier.set_available(backend::server_available()),
+                Err(err) => log::warn!("Failed to watch notification server: {err:?}"),
+            }
+        }
+        if !self.notifier.available() {
+            log::warn!("No notification server, notifications will be queued until one appears");
+        }
+    }
+
+    /// Keep notification until a notification server appears, dropping the oldest one if full
+    fn queue_notification(&mut self, message: &gotify::Message) {
+        log::info!(
+            "No notification server, queuing notification for message {}",
+            message.id
         );
+        if let Some(dropped) = self.notif_queue.push(message) {
+            log::warn!("Dropping queued notification for message {}", dropped.id);
+        }
     }
 
-    if let Some(on_msg_command) = on_msg_command
-        && let Err(e) = run_on_msg_command(message, on_msg_command)
-    {
-        log::warn!("Command {on_msg_command:?} failed with error: {e:?}");
+    /// Track notification server availability, and show queued notifications when one appears
+    fn server_changed(
+        &mut self,
+        available: bool,
+        client: &mut gotify::Client,
+    ) -> anyhow::Result<()> {
+        // Server may exit, and be started again by D-Bus activation on next notification
+        self.notifier
+            .set_available(available || backend::server_available());
+        if !self.notifier.available() {
+            log::warn!("Notification server is gone");
+            return Ok(());
+        }
+        log::info!("Notification server is available");
+        self.notifier.refresh_capabilities();
+        let queued = self.notif_queue.take();
+        if !queued.is_empty() {
+            log::info!("Showing {} queued notification(s)", queued.len());
+        }
+        for message in queued {
+            self.try_step(Step::Notify, &message, 0, client)?;
+        }
+        Ok(())
     }
 
-    if delete {
-        client.delete_message(message.id)?;
+    /// Configured snooze duration
+    fn snooze_duration(&self) -> Duration {
+        self.notif_cfg
+            .snooze_minutes
+            .map_or(notif::DEFAULT_SNOOZE, |m| {
+                Duration::from_secs(m.saturating_mul(60))
+            })
     }
 
-    Ok(())
+    /// Snooze recently handled message by id, and close its notification until then
+    fn snooze_recent(&mut self, msg_id: i64, duration: Option<Duration>) -> anyhow::Result<()> {
+        let (message, notif_id) = self
+            .recent
+            .iter()
+            .rev()
+            .find(|(m, _)| m.id == msg_id)
+            .cloned()
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Unknown message {msg_id}, only the last {RECENT_CAPACITY} messages can be snoozed"
+                )
+            })?;
+        let duration = duration.unwrap_or_else(|| self.snooze_duration());
+        self.snoozed.push(message, duration)?;
+        if let Some(notif_id) = notif_id
+            && let Err(err) = self.notifier.close(notif_id)
+        {
+            log::warn!("Failed to close notification of message {msg_id}: {err:?}");
+        }
+        Ok(())
+    }
+
+    /// Remind and escalate alerts that are not acknowledged
+    fn process_alerts(&mut self) {
+        let now = Instant::now();
+        for reminder in self.alerts.due_reminders(now) {
+            log::info!(
+                "Alert for message {} is not acknowledged, reminder {}",
+                reminder.message.id,
+                reminder.count
+            );
+            if !self.notifier.available() {
+                log::info!("No notification server, skipping reminder");
+                continue;
+            }
+            let update = notif::Update {
+                notif_id: reminder.notif_id,
+                note: format!("reminder {}", reminder.count),
+            };
+            match notif::show(
+                &reminder.message,
+                &self.notif_cfg,
+                &mut self.notif_groups,
+                notif::Options {
+                    update: Some(&update),
+                    alert: true,
+                },
+                &self.server_host,
+                self.notifier.as_mut(),
+                &mut self.actions,
+            ) {
+                Ok(Some(notif_id)) => self.alerts.set_notif_id(reminder.message.id, notif_id),
+                Ok(None) => {}
+                Err(err) => log::warn!("Failed to show alert reminder: {err:?}"),
+            }
+        }
+        for message in self.alerts.due_escalations(now) {
+            log::warn!(
+                "Alert for message {} is not acknowledged, escalating",
+                message.id
+            );
+            if let Some(escalation_command) = &self.escalation_command
+                && let Err(e) = run_msg_command(&message, escalation_command)
+            {
+                log::warn!("Command {escalation_command:?} failed with error: {e:?}");
+            }
+        }
+    }
+
+    /// Process timers that are due: retries, snoozes, alerts, and end of notification floods
+    fn process_timers(&mut self, client: &mut gotify::Client) -> anyhow::Result<()> {
+        self.process_alerts();
+        while let Some(mut message) = self.snoozed.pop_due(SystemTime::now()) {
+            log::info!("Snooze of message {} is over", message.id);
+            // App data is not persisted
+            client.try_set_message_app(&mut message);
+            self.try_step(Step::Notify, &message, 0, client)?;
+        }
+        for (app_name, count) in self.limiter.ended_floods(Instant::now()) {
+            log::info!("Flood from {app_name} is over, {count} notification(s) were suppressed");
+            if let Err(err) = notif::show_suppressed(self.notifier.as_mut(), &app_name, count) {
+                log::warn!("Failed to show notification: {err:?}");
+            }
+        }
+        while let Some(((message, step), attempts)) = self.retries.pop_due(Instant::now()) {
+            log::info!(
+                "Retrying {step:?} for message {} (attempt {})",
+                message.id,
+                attempts + 1
+            );
+            self.try_step(step, &message, attempts, client)?;
+        }
+        Ok(())
+    }
+
+    /// Maximum duration to wait for a message before processing timers
+    fn timeout(&self) -> Option<Duration> {
+        let now = Instant::now();
+        [
+            self.retries.next_retry(),
+            self.limiter.next_flood_end(),
+            self.alerts.next_due(),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|t| t.saturating_duration_since(now))
+        .chain(self.snoozed.next_due().map(|t| {
+            t.duration_since(SystemTime::now())
+                .unwrap_or(Duration::ZERO)
+        }))
+        .min()
+    }
 }
 
-/// Program entry point
-fn main() -> anyhow::Result<()> {
-    // Init logger
-    simple_logger::SimpleLogger::new()
-        .with_level(if cfg!(debug_assertions) {
-            log::LevelFilter::Debug
-        } else {
-            log::LevelFilter::Info
-        })
-        .env()
-        .init()
-        .context("Failed to init logger")?;
+/// Load application registry from cache
+fn load_apps(overrides: Vec<config::AppConfig>) -> anyhow::Result<apps::Registry> {
+    let xdg_dirs = xdg::BaseDirectories::with_prefix(env!("CARGO_PKG_NAME"));
+    Ok(apps::Registry::load(
+        xdg_dirs
+            .place_cache_file("apps.json")
+            .context("Failed to create cache directory")?,
+        overrides,
+    ))
+}
+
+/// Get state file path
+fn state_filepath(filename: &str) -> anyhow::Result<std::path::PathBuf> {
+    let xdg_dirs = xdg::BaseDirectories::with_prefix(env!("CARGO_PKG_NAME"));
+    xdg_dirs
+        .place_state_file(filename)
+        .context("Failed to create state directory")
+}
+
+/// Run cache management command
+fn run_cache_command(command: &str) -> anyhow::Result<()> {
+    // The daemon would keep using the cache, and overwrite the application registry
+    anyhow::ensure!(
+        !control::daemon_running(),
+        "The daemon is running, stop it before managing the cache"
+    );
+    let mut apps = load_apps(vec![])?;
+    match command {
+        "clear" => {
+            let count = apps.clear()?;
+            log::info!("Removed {count} cache file(s)");
+        }
+        "prune" => {
+            // Get up to date application list, to know which images are still used
+            let cfg = config::parse().context("Failed to read config")?;
+            let api = gotify::Api::new(&cfg.gotify.url, gotify::request_headers(&cfg.gotify)?)?;
+            apps.update(
+                api.applications()
+                    .context("Failed to get applications from server")?,
+            );
+            apps.save()?;
+            let count = apps.prune()?;
+            log::info!("Removed {count} orphan cache file(s)");
+        }
+        _ => anyhow::bail!("Unknown cache command {command:?}, expected 'clear' or 'prune'"),
+    }
+    Ok(())
+}
 
+/// Run daemon, only returns on fatal error
+fn run() -> anyhow::Result<()> {
     // Parse config
     let cfg = config::parse().context("Failed to read config")?;
-    let token = cfg.gotify.token.fetch()?;
-    let on_msg_command = match cfg.action.on_msg_command {
-        None => None,
-        Some(cmd) => Some(
-            shlex::split(&cmd)
-                .with_context(|| format!("Failed to split command arguments for {cmd:?}"))?
-                .split_first()
-                .map(|t| (t.0.to_owned(), t.1.to_owned()))
-                .ok_or_else(|| anyhow::anyhow!("Empty command"))?,
-        ),
-    };
+    let headers = gotify::request_headers(&cfg.gotify)?;
+    let on_msg_command = parse_command(cfg.action.on_msg_command)?;
+    let escalation_command = parse_command(cfg.notification.alert.escalation_command.clone())?;
 
     // Keep last handled message id
     let last_msg_id = Rc::new(RefCell::new(None));
 
+    // Application metadata, persisted across restarts
+    let apps = Rc::new(RefCell::new(load_apps(cfg.apps)?));
+
+    let actions = actions::Dispatcher::new();
+    let notifier = backend::new(&cfg.notification, actions.action_sender())
+        .context("Failed to setup notification backend")?;
+    let mut handler = Handler {
+        on_msg_command,
+        escalation_command,
+        auto_delete: cfg.gotify.auto_delete,
+        retries: RetryQueue::new(RETRY_QUEUE_CAPACITY, RETRY_MAX_ATTEMPTS),
+        notif_groups: notif::Groups::default(),
+        notifier,
+        notif_queue: notif::Queue::default(),
+        limiter: ratelimit::Limiter::new(cfg.notification.rate_limit.clone()),
+        deduper: dedupe::Deduper::new(Duration::from_secs(cfg.notification.dedupe.window)),
+        actions,
+        snoozed: snooze::Queue::load(state_filepath("snoozed.json")?),
+        recent: VecDeque::with_capacity(RECENT_CAPACITY),
+        alerts: alerts::Alerts::new(&cfg.notification.alert),
+        notif_cfg: cfg.notification,
+        server_host: cfg.gotify.url.host_str().unwrap_or_default().to_owned(),
+    };
+
+    if let Err(err) = control::listen(handler.actions.command_sender()) {
+        log::warn!("Failed to listen for commands: {err:?}");
+    }
+    handler.watch_notif_server();
+
     // Connect loop
     loop {
         // Connect
-        let mut client = gotify::Client::connect(&cfg.gotify, &token, Rc::clone(&last_msg_id))
-            .context("Failed to setup or connect client")?;
+        let mut client = gotify::Client::connect(
+            &cfg.gotify,
+            &headers,
+            Rc::clone(&last_msg_id),
+            Rc::clone(&apps),
+            handler.notifier.as_mut(),
+        )
+        .context("Failed to setup or connect client")?;
         log::info!("Connected to {}", cfg.gotify.url);
+        handler.actions.set_waker(client.waker());
 
         // Handle missed messages
-        let missed_messages = client
-            .get_missed_messages()
-            .context("Failed to get missed messages")?;
+        let missed_messages = match client.get_missed_messages() {
+            Ok(m) => m,
+            Err(e) => match retry::classify(&e) {
+                retry::ErrorClass::Transient => {
+                    log::warn!(
+                        "Failed to get missed messages: {e:?}, will try to reconnect in {CATCH_UP_RETRY_DELAY:?}"
+                    );
+                    thread::sleep(CATCH_UP_RETRY_DELAY);
+                    continue;
+                }
+                retry::ErrorClass::Permanent => {
+                    log::error!("Failed to get missed messages: {e:?}");
+                    vec![]
+                }
+                retry::ErrorClass::Fatal => {
+                    return Err(e.context("Failed to get missed messages"));
+                }
+            },
+        };
         if !missed_messages.is_empty() {
             log::info!("Catching up {} missed message(s)", missed_messages.len());
             for msg in missed_messages {
-                handle_message(
-                    &msg,
-                    cfg.notification.min_priority,
-                    on_msg_command.as_ref(),
-                    cfg.gotify.auto_delete,
-                    &mut client,
-                )
-                .context("Failed to handle message")?;
+                handler
+                    .handle_message(&msg, &mut client)
+                    .context("Failed to handle message")?;
             }
         }
 
-        // Blocking message loop
+        // Blocking message loop, woken up for timers and notification actions
         loop {
-            let res = client.get_message();
-            let msg = match res {
-                Ok(m) => m,
-                #[expect(clippy::ref_patterns)]
-                Err(ref e) => {
-                    if e.downcast_ref::<gotify::NeedsReconnect>().is_some() {
+            handler.process_requests(&mut client)?;
+            handler.process_timers(&mut client)?;
+
+            let msg = match client.get_message(handler.timeout()) {
+                Ok(Some(m)) => m,
+                Ok(None) => continue,
+                Err(e) => match retry::classify(&e) {
+                    retry::ErrorClass::Transient => {
                         log::warn!("Error while waiting for message: {e}, will try to reconnect");
                         break;
                     }
-                    res.context("Failed to get message")?;
-                    unreachable!();
-                }
+                    retry::ErrorClass::Permanent => {
+                        log::error!("Failed to get message: {e:?}");
+                        continue;
+                    }
+                    retry::ErrorClass::Fatal => {
+                        return Err(e.context("Failed to get message"));
+                    }
+                },
             };
 
-            handle_message(
-                &msg,
-                cfg.notification.min_priority,
-                on_msg_command.as_ref(),
-                cfg.gotify.auto_delete,
-                &mut client,
-            )
-            .context("Failed to handle message")?;
+            handler
+                .handle_message(&msg, &mut client)
+                .context("Failed to handle message")?;
         }
     }
 }
+
+/// Program entry point
+fn main() -> anyhow::Result<()> {
+    // Init logger
+    simple_logger::SimpleLogger::new()
+        .with_level(if cfg!(debug_assertions) {
+            log::LevelFilter::Debug
+        } else {
+            log::LevelFilter::Info
+        })
+        .env()
+        .init()
+        .context("Failed to init logger")?;
+
+    // Parse command line
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        [] => run(),
+        ["cache", command] => run_cache_command(command),
+        ["snooze" | "ack", _] | ["snooze", _, _] => control::send(&args.join(" ")),
+        _ => anyhow::bail!(
+            "Usage: {} [cache clear|cache prune|snooze <message id> [minutes]|ack <message id>]",
+            env!("CARGO_PKG_NAME")
+        ),
+    }
+}
@@ -1,43 +1,333 @@
 //! Desktop notification
 
-use crate::gotify;
-
-/// Name of the XDG Desktop entry, without the .desktop suffix
-const DESKTOP_ENTRY_NAME: &str = env!("CARGO_PKG_NAME");
-
-/// Show notification
-pub(crate) fn show(msg: &gotify::Message) -> anyhow::Result<()> {
-    #[cfg(all(unix, not(target_os = "macos")))]
-    let urgency = match msg.priority {
-        0..=3 => notify_rust::Urgency::Low,
-        4..=7 => notify_rust::Urgency::Normal,
-        8..=10 => notify_rust::Urgency::Critical,
-        v => {
-            log::warn!("Unexpected urgency value {v}");
-            notify_rust::Urgency::Normal
-        }
-    };
+use std::{
+    collections::{HashMap, VecDeque},
+    mem,
+    time::{Duration, Instant},
+};
+
+use crate::{actions, backend, config, gotify, template};
+
+/// Default snooze duration
+pub(crate) const DEFAULT_SNOOZE: Duration = Duration::from_secs(15 * 60);
+
+/// Notification app name, if Gotify app name is unknown
+const APP_NAME: &str = "Gotify Desktop";
+
+/// Maximum number of notifications waiting for a notification server
+const QUEUE_CAPACITY: usize = 100;
+
+/// Notification recently shown for an app, that later messages can update
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Group {
+    /// Notification server id
+    notif_id: u32,
+    /// Number of messages coalesced in the notification
+    count: usize,
+    /// When the notification was last updated
+    updated_at: Instant,
+}
 
-    let mut notif = notify_rust::Notification::new();
-    notif.summary(&msg.title).body(&msg.text);
-    #[cfg(all(unix, not(target_os = "macos")))]
-    notif
-        .urgency(urgency)
-        .appname("Gotify Desktop")
-        .hint(notify_rust::Hint::DesktopEntry(
-            DESKTOP_ENTRY_NAME.to_owned(),
-        ));
-    if let Some(img_filepath) = &msg.app_img_filepath.as_ref() {
-        notif.icon(
-            img_filepath
-                .to_str()
-                .ok_or_else(|| anyhow::anyhow!("Unable to convert path to string"))?,
+/// Recent notifications by app id, to coalesce bursts of messages from the same app
+#[derive(Default)]
+pub(crate) struct Groups {
+    /// Last notification of each app
+    groups: HashMap<i64, Group>,
+}
+
+impl Groups {
+    /// Get notification of an app that is recent enough to be updated
+    fn active(&self, app_id: i64, window: Duration, now: Instant) -> Option<Group> {
+        self.groups
+            .get(&app_id)
+            .filter(|g| now.saturating_duration_since(g.updated_at) < window)
+            .copied()
+    }
+
+    /// Record notification shown for an app, and forget those too old to be updated
+    fn record(&mut self, app_id: i64, notif_id: u32, count: usize, window: Duration, now: Instant) {
+        self.groups
+            .retain(|_, g| now.saturating_duration_since(g.updated_at) < window);
+        self.groups.insert(
+            app_id,
+            Group {
+                notif_id,
+                count,
+                updated_at: now,
+            },
         );
+    }
+}
+
+/// Notifications waiting for a notification server
+#[derive(Default)]
+pub(crate) struct Queue {
+    /// Messages to notify, oldest first
+    messages: VecDeque<gotify::Message>,
+}
+
+impl Queue {
+    /// Queue message, return the oldest one if it was dropped because the queue is full
+    pub(crate) fn push(&mut self, msg: &gotify::Message) -> Option<gotify::Message> {
+        let dropped = if self.messages.len() >= QUEUE_CAPACITY {
+            self.messages.pop_front()
+        } else {
+            None
+        };
+        self.messages.push_back(msg.clone());
+        dropped
+    }
+
+    /// Take all queued messages, oldest first
+    pub(crate) fn take(&mut self) -> VecDeque<gotify::Message> {
+        mem::take(&mut self.messages)
+    }
+}
+
+/// Update of the notification previously shown for a message, for repetitions or reminders
+#[derive(Clone, Debug)]
+pub(crate) struct Update {
+    /// Notification server id, if it supports updating notifications
+    pub notif_id: Option<u32>,
+    /// Note appended to the summary
+    pub note: String,
+}
+
+/// How to show a notification
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Options<'a> {
+    /// Update of the notification previously shown for the message, instead of a new one
+    pub update: Option<&'a Update>,
+    /// Show as an alert, resident and with an acknowledge button
+    pub alert: bool,
+}
+
+/// Show notification, updating the previous one of the same app if it is recent enough,
+/// or the given one. Alerts are resident, and can be acknowledged.
+/// Features the notification backend does not support are left out.
+/// Return the notification id, if the notification backend supports updating it
+pub(crate) fn show(
+    msg: &gotify::Message,
+    cfg: &config::NotificationConfig,
+    groups: &mut Groups,
+    options: Options<'_>,
+    server: &str,
+    notifier: &mut dyn backend::Notifier,
+    dispatcher: &mut actions::Dispatcher,
+) -> anyhow::Result<Option<u32>> {
+    let Options { update, alert } = options;
+    let window = Duration::from_secs(cfg.coalesce_window);
+    let now = Instant::now();
+    let group = if update.is_some() {
+        None
+    } else {
+        groups.active(msg.appid, window, now)
+    };
+    let count = group.map_or(1, |g| g.count + 1);
+
+    let caps = notifier.capabilities();
+    let (summary, body) = render_templates(msg, cfg, server, caps);
+    let summary = if let Some(update) = update {
+        format!("{summary} ({})", update.note)
+    } else if count > 1 {
+        format!("{summary} ({count} messages)")
     } else {
-        notif.icon(DESKTOP_ENTRY_NAME);
+        summary
+    };
+    let prio_cfg = cfg.priority(msg.priority);
+    if prio_cfg.is_none() {
+        log::debug!("No priority range matches priority {}", msg.priority);
+    }
+    let mut buttons = Vec::new();
+    if caps.actions {
+        buttons.extend(cfg.actions.iter().map(|a| button(*a, cfg)));
+        if alert {
+            buttons.push(button(config::NotificationAction::Acknowledge, cfg));
+        }
     }
+    let notif = backend::Notification {
+        replaces_id: update
+            .and_then(|u| u.notif_id)
+            .or_else(|| group.map(|g| g.notif_id)),
+        app_name: msg.app_name.clone().unwrap_or_else(|| APP_NAME.to_owned()),
+        summary,
+        body,
+        icon: msg.app_icon.clone(),
+        urgency: prio_cfg.and_then(|p| p.urgency),
+        category: prio_cfg.and_then(|p| p.category.clone()),
+        timeout: prio_cfg.and_then(|p| p.timeout),
+        sound: msg.app_sound.clone().or_else(|| prio_cfg?.sound.clone()),
+        buttons,
+        resident: alert,
+    };
+
+    let notif_id = notifier.show(&notif)?;
+
+    if let Some(notif_id) = notif_id {
+        if !notif.buttons.is_empty() {
+            dispatcher.track(notif_id, msg);
+        }
+        if update.is_none() && !window.is_zero() {
+            groups.record(msg.appid, notif_id, count, window, now);
+        }
+    }
+
+    Ok(notif_id)
+}
 
-    notif.show()?;
+/// Get notification action button, with its label
+fn button(action: config::NotificationAction, cfg: &config::NotificationConfig) -> backend::Button {
+    let label = match action {
+        config::NotificationAction::Delete => "Delete on server".to_owned(),
+        config::NotificationAction::Snooze => format!(
+            "Snooze {} min",
+            cfg.snooze_minutes.unwrap_or(DEFAULT_SNOOZE.as_secs() / 60)
+        ),
+        config::NotificationAction::Copy => "Copy text".to_owned(),
+        config::NotificationAction::Open => "Open in Gotify".to_owned(),
+        config::NotificationAction::Acknowledge => "Acknowledge".to_owned(),
+    };
+    backend::Button { action, label }
+}
+
+/// Render notification summary and body, with app templates or global ones.
+/// An empty summary, for example from a message without title, falls back to the app name, or id.
+/// Body markup is escaped, unless the app is trusted,
+/// and markup the notification server does not support is removed
+fn render_templates(
+    msg: &gotify::Message,
+    cfg: &config::NotificationConfig,
+    server: &str,
+    caps: backend::Capabilities,
+) -> (String, String) {
+    let now = jiff::Timestamp::now();
+    let tz = jiff::tz::TimeZone::system();
+    let summary = msg
+        .app_summary
+        .as_deref()
+        .or(cfg.summary.as_deref())
+        .unwrap_or(template::DEFAULT_SUMMARY);
+    let body = msg
+        .app_body
+        .as_deref()
+        .or(cfg.body.as_deref())
+        .unwrap_or(template::DEFAULT_BODY);
+    let mut summary = template::sanitize(&template::render(summary, msg, server, now, &tz), false);
+    if summary.is_empty() {
+        summary = template::app_name(msg);
+    }
+    let body = template::render(body, msg, server, now, &tz);
+    // Text is shown as is by notification servers that do not interpret body markup,
+    // unsupported markup is stripped before truncating, so that no tag is cut
+    let body = match (caps.body_markup, msg.app_markup) {
+        (false, false) => template::sanitize(&body, false),
+        (false, true) => template::sanitize(&template::strip_markup(&body), false),
+        (true, false) => template::escape_markup(&template::sanitize(&body, false)),
+        (true, true) if caps.body_images => template::sanitize(&body, true),
+        (true, true) => template::sanitize(&template::strip_images(&body), true),
+    };
+    (summary, body)
+}
+
+/// Show notification summarizing messages suppressed by rate limiting
+pub(crate) fn show_suppressed(
+    notifier: &mut dyn backend::Notifier,
+    app_name: &str,
+    count: usize,
+) -> anyhow::Result<()> {
+    if !notifier.available() {
+        log::info!("No notification server, not showing suppressed message count");
+        return Ok(());
+    }
+    notifier.show(&backend::Notification {
+        app_name: APP_NAME.to_owned(),
+        summary: format!("{count} message(s) suppressed from {app_name}"),
+        body: "Notification rate limit was reached".to_owned(),
+        ..backend::Notification::default()
+    })?;
+    Ok(())
+}
 
+/// Show daemon status notification, for problems the user should know about
+pub(crate) fn show_status(
+    notifier: &mut dyn backend::Notifier,
+    summary: &str,
+    body: &str,
+) -> anyhow::Result<()> {
+    if !notifier.available() {
+        log::info!("No notification server, not showing status {summary:?}");
+        return Ok(());
+    }
+    notifier.show(&backend::Notification {
+        app_name: APP_NAME.to_owned(),
+        summary: summary.to_owned(),
+        body: body.to_owned(),
+        urgency: Some(config::Urgency::Critical),
+        ..backend::Notification::default()
+    })?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coalesce_groups() {
+        let window = Duration::from_secs(10);
+        let now = Instant::now();
+        let mut groups = Groups::default();
+        assert!(groups.active(1, window, now).is_none());
+
+        groups.record(1, 42, 1, window, now);
+        let later = now + Duration::from_secs(5);
+        assert_eq!(
+            groups.active(1, window, later),
+            Some(Group {
+                notif_id: 42,
+                count: 1,
+                updated_at: now
+            })
+        );
+        assert!(groups.active(2, window, later).is_none());
+
+        // Window slides on each update
+        groups.record(1, 42, 2, window, later);
+        let much_later = now + Duration::from_secs(12);
+        assert_eq!(groups.active(1, window, much_later).unwrap().count, 2);
+        assert!(groups.active(1, window, much_later + window).is_none());
+
+        // Old groups are forgotten
+        groups.record(2, 43, 1, window, much_later + window);
+        assert_eq!(groups.groups.len(), 1);
+    }
+
+    #[test]
+    fn summary_fallback() {
+        let cfg = config::NotificationConfig::default();
+        let mut msg = gotify::Message {
+            title: String::new(),
+            ..gotify::test_message(12, 3, 5)
+        };
+        let caps = backend::Capabilities::default();
+        assert_eq!(render_templates(&msg, &cfg, "", caps).0, "App 3");
+        msg.app_name = Some("Backups".to_owned());
+        assert_eq!(render_templates(&msg, &cfg, "", caps).0, "Backups");
+    }
+
+    #[test]
+    fn queue_messages() {
+        let mut queue = Queue::default();
+        assert!(queue.take().is_empty());
+        for id in 0..QUEUE_CAPACITY {
+            let id = i64::try_from(id).unwrap();
+            assert!(queue.push(&gotify::test_message(id, 1, 5)).is_none());
+        }
+        // Oldest message is dropped when full
+        let dropped = queue.push(&gotify::test_message(100, 1, 5)).unwrap();
+        assert_eq!(dropped.id, 0);
+        let ids: Vec<i64> = queue.take().iter().map(|m| m.id).collect();
+        assert_eq!(ids, (1..=100).collect::<Vec<_>>());
+        assert!(queue.take().is_empty());
+    }
+}
@@ -0,0 +1,217 @@
+//! Notification rate limiting, to protect the desktop from flooding senders
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use crate::{config, gotify, template};
+
+/// Delay without suppressed message after which a flood is considered over
+const FLOOD_END_DELAY: Duration = Duration::from_secs(30);
+
+/// Token bucket
+struct Bucket {
+    /// Maximum number of tokens
+    capacity: f64,
+    /// Tokens added per second
+    rate: f64,
+    /// Available tokens
+    tokens: f64,
+    /// Last time tokens were updated
+    updated_at: Instant,
+}
+
+impl Bucket {
+    /// Create full bucket
+    fn new(cfg: &config::BucketConfig, now: Instant) -> Self {
+        let capacity = f64::from(cfg.burst);
+        Self {
+            capacity,
+            rate: f64::from(cfg.per_minute) / 60.0,
+            tokens: capacity,
+            updated_at: now,
+        }
+    }
+
+    /// Add tokens for time elapsed since last update
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens = elapsed.mul_add(self.rate, self.tokens).min(self.capacity);
+        self.updated_at = now;
+    }
+
+    /// Is a token available?
+    fn has_token(&self) -> bool {
+        self.tokens >= 1.0
+    }
+
+    /// Consume a token, which must be available
+    fn take(&mut self) {
+        self.tokens -= 1.0;
+    }
+}
+
+/// Messages suppressed for an app during a flood
+struct Suppressed {
+    /// App name, for the summary notification
+    app_name: String,
+    /// Number of suppressed messages
+    count: usize,
+    /// When the last message was suppressed
+    last_at: Instant,
+}
+
+/// Global and per app notification rate limiter
+pub(crate) struct Limiter {
+    /// Configuration
+    cfg: config::RateLimitConfig,
+    /// Bucket shared by all apps
+    global: Option<Bucket>,
+    /// Buckets by app id
+    apps: HashMap<i64, Bucket>,
+    /// Suppressed messages by app id
+    suppressed: HashMap<i64, Suppressed>,
+}
+
+impl Limiter {
+    /// Create limiter, that allows everything if no limit is configured
+    pub(crate) fn new(cfg: config::RateLimitConfig) -> Self {
+        let now = Instant::now();
+        Self {
+            global: cfg.global.as_ref().map(|c| Bucket::new(c, now)),
+            cfg,
+            apps: HashMap::new(),
+            suppressed: HashMap::new(),
+        }
+    }
+
+    /// Can a notification be shown for this message? Records suppression if not
+    pub(crate) fn allow(&mut self, msg: &gotify::Message, now: Instant) -> bool {
+        if let Some(global) = &mut self.global {
+            global.refill(now);
+        }
+        let app = self.cfg.per_app.as_ref().map(|c| {
+            let bucket = self
+                .apps
+                .entry(msg.appid)
+                .or_insert_with(|| Bucket::new(c, now));
+            bucket.refill(now);
+            bucket
+        });
+
+        let allowed = self.global.as_ref().is_none_or(Bucket::has_token)
+            && app.as_ref().is_none_or(|b| b.has_token());
+        if allowed {
+            if let Some(global) = &mut self.global {
+                global.take();
+            }
+            if let Some(app) = app {
+                app.take();
+            }
+        } else {
+            let suppressed = self.suppressed.entry(msg.appid).or_insert_with(|| {
+                log::warn!("Rate limit reached for app {}", msg.appid);
+                Suppressed {
+                    app_name: template::app_name(msg),
+                    count: 0,
+                    last_at: now,
+                }
+            });
+            suppressed.count += 1;
+            suppressed.last_at = now;
+        }
+        allowed
+    }
+
+    /// Get app names and suppressed message counts of floods that have ended
+    pub(crate) fn ended_floods(&mut self, now: Instant) -> Vec<(String, usize)> {
+        let ended: Vec<i64> = self
+            .suppressed
+            .iter()
+            .filter(|(_, s)| now.saturating_duration_since(s.last_at) >= FLOOD_END_DELAY)
+            .map(|(app_id, _)| *app_id)
+            .collect();
+        ended
+            .into_iter()
+            .filter_map(|app_id| self.suppressed.remove(&app_id))
+            .map(|s| (s.app_name, s.count))
+            .collect()
+    }
+
+    /// Time when the next flood will be considered over, if any
+    pub(crate) fn next_flood_end(&self) -> Option<Instant> {
+        self.suppressed
+            .values()
+            .map(|s| s.last_at + FLOOD_END_DELAY)
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_refill() {
+        let now = Instant::now();
+        let mut bucket = Bucket::new(
+            &config::BucketConfig {
+                burst: 2,
+                per_minute: 60,
+            },
+            now,
+        );
+        bucket.take();
+        bucket.take();
+        assert!(!bucket.has_token());
+        bucket.refill(now + Duration::from_millis(500));
+        assert!(!bucket.has_token());
+        bucket.refill(now + Duration::from_secs(10));
+        assert!(bucket.has_token());
+        bucket.take();
+        bucket.take();
+        assert!(!bucket.has_token());
+    }
+
+    #[test]
+    fn limit_per_app() {
+        let mut limiter = Limiter::new(config::RateLimitConfig {
+            global: Some(config::BucketConfig {
+                burst: 3,
+                per_minute: 1,
+            }),
+            per_app: Some(config::BucketConfig {
+                burst: 2,
+                per_minute: 1,
+            }),
+        });
+        let now = Instant::now();
+        assert!(limiter.allow(&gotify::test_message(1, 1, 5), now));
+        assert!(limiter.allow(&gotify::test_message(1, 1, 5), now));
+        assert!(!limiter.allow(&gotify::test_message(1, 1, 5), now));
+        assert!(!limiter.allow(&gotify::test_message(1, 1, 5), now));
+        assert!(limiter.allow(&gotify::test_message(1, 2, 5), now));
+        assert!(!limiter.allow(&gotify::test_message(1, 2, 5), now));
+
+        assert_eq!(limiter.next_flood_end(), Some(now + FLOOD_END_DELAY));
+        assert!(limiter.ended_floods(now).is_empty());
+        let mut ended = limiter.ended_floods(now + FLOOD_END_DELAY);
+        ended.sort();
+        assert_eq!(
+            ended,
+            vec![("App 1".to_owned(), 2), ("App 2".to_owned(), 1)]
+        );
+        assert!(limiter.next_flood_end().is_none());
+    }
+
+    #[test]
+    fn no_limit() {
+        let mut limiter = Limiter::new(config::RateLimitConfig::default());
+        let now = Instant::now();
+        for _ in 0..100 {
+            assert!(limiter.allow(&gotify::test_message(1, 1, 5), now));
+        }
+        assert!(limiter.next_flood_end().is_none());
+    }
+}
@@ -0,0 +1,179 @@
+//! Failure classification & bounded retry queue
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use crate::gotify;
+
+/// How to react to a failure
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum ErrorClass {
+    /// Temporary failure (network, notification server restart...), worth retrying later
+    Transient,
+    /// Failure that will not go away by retrying, log and skip
+    Permanent,
+    /// Configuration level failure, the daemon can not work without user intervention
+    Fatal,
+}
+
+/// Classify error by looking at the first known error type in its chain
+pub(crate) fn classify(err: &anyhow::Error) -> ErrorClass {
+    for cause in err.chain() {
+        if let Some(ureq_err) = cause.downcast_ref::<ureq::Error>() {
+            return match ureq_err {
+                ureq::Error::StatusCode(401 | 403) => ErrorClass::Fatal,
+                ureq::Error::StatusCode(408 | 429 | 500..) => ErrorClass::Transient,
+                ureq::Error::StatusCode(_)
+                | ureq::Error::BadUri(_)
+                | ureq::Error::Http(_)
+                | ureq::Error::InvalidProxyUrl => ErrorClass::Permanent,
+                _ => ErrorClass::Transient,
+            };
+        } else if cause.is::<serde_json::Error>() {
+            return ErrorClass::Permanent;
+        } else if cause.is::<gotify::NeedsReconnect>()
+            || cause.is::<tungstenite::Error>()
+            || cause.is::<notify_rust::error::Error>()
+            || cause.is::<std::io::Error>()
+        {
+            return ErrorClass::Transient;
+        }
+        // D-Bus failures of the desktop portal backend
+        #[cfg(all(unix, not(target_os = "macos")))]
+        if cause.is::<zbus::Error>() {
+            return ErrorClass::Transient;
+        }
+    }
+    ErrorClass::Permanent
+}
+
+/// Delay before first retry, doubled on each subsequent attempt
+const BASE_DELAY: Duration = Duration::from_secs(2);
+
+/// Maximum delay between retries
+const MAX_DELAY: Duration = Duration::from_secs(5 * 60);
+
+/// Item waiting to be retried
+struct Entry<T> {
+    /// The item itself
+    item: T,
+    /// Number of failed attempts so far
+    attempts: u32,
+    /// When to try again
+    retry_at: Instant,
+}
+
+/// Bounded queue of items to retry with exponential backoff
+pub(crate) struct Queue<T> {
+    /// Pending items, oldest first
+    entries: VecDeque<Entry<T>>,
+    /// Maximum number of pending items
+    capacity: usize,
+    /// Maximum number of attempts for a single item
+    max_attempts: u32,
+}
+
+impl<T> Queue<T> {
+    /// Create empty queue
+    pub(crate) fn new(capacity: usize, max_attempts: u32) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+            max_attempts,
+        }
+    }
+
+    /// Schedule retry for an item that failed `attempts` times so far.
+    /// Return an item that was given up on, either this one if it has exhausted its attempts,
+    /// or the oldest one if the queue is full
+    pub(crate) fn push(&mut self, item: T, attempts: u32) -> Option<T> {
+        if attempts >= self.max_attempts {
+            return Some(item);
+        }
+        let evicted = if self.entries.len() >= self.capacity {
+            self.entries.pop_front().map(|e| e.item)
+        } else {
+            None
+        };
+        let delay = BASE_DELAY
+            .saturating_mul(2_u32.saturating_pow(attempts.saturating_sub(1)))
+            .min(MAX_DELAY);
+        self.entries.push_back(Entry {
+            item,
+            attempts,
+            retry_at: Instant::now() + delay,
+        });
+        evicted
+    }
+
+    /// Remove and return an item whose retry time has come, with its failed attempt count
+    pub(crate) fn pop_due(&mut self, now: Instant) -> Option<(T, u32)> {
+        let idx = self.entries.iter().position(|e| e.retry_at <= now)?;
+        self.entries.remove(idx).map(|e| (e.item, e.attempts))
+    }
+
+    /// Time of the next retry, if any
+    pub(crate) fn next_retry(&self) -> Option<Instant> {
+        self.entries.iter().map(|e| e.retry_at).min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_errors() {
+        assert_eq!(
+            classify(&anyhow::Error::from(ureq::Error::StatusCode(401))),
+            ErrorClass::Fatal
+        );
+        assert_eq!(
+            classify(&anyhow::Error::from(ureq::Error::StatusCode(404))),
+            ErrorClass::Permanent
+        );
+        assert_eq!(
+            classify(&anyhow::Error::from(ureq::Error::StatusCode(503)).context("Failed")),
+            ErrorClass::Transient
+        );
+        assert_eq!(
+            classify(&anyhow::Error::from(ureq::Error::HostNotFound)),
+            ErrorClass::Transient
+        );
+        assert_eq!(
+            classify(&serde_json::from_str::<i64>("{").unwrap_err().into()),
+            ErrorClass::Permanent
+        );
+        assert_eq!(
+            classify(&anyhow::anyhow!("Something else")),
+            ErrorClass::Permanent
+        );
+    }
+
+    #[test]
+    fn queue_backoff() {
+        let mut queue = Queue::new(10, 3);
+        assert!(queue.next_retry().is_none());
+        assert!(queue.push("a", 1).is_none());
+        let now = Instant::now();
+        assert!(queue.pop_due(now).is_none());
+        assert!(queue.next_retry().unwrap() > now);
+        assert_eq!(queue.pop_due(now + BASE_DELAY), Some(("a", 1)));
+        assert!(queue.pop_due(now + MAX_DELAY).is_none());
+    }
+
+    #[test]
+    fn queue_bounds() {
+        let mut queue = Queue::new(2, 3);
+        assert_eq!(queue.push("a", 3), Some("a"));
+        assert!(queue.push("b", 1).is_none());
+        assert!(queue.push("c", 2).is_none());
+        assert_eq!(queue.push("d", 1), Some("b"));
+        let later = Instant::now() + MAX_DELAY;
+        assert_eq!(queue.pop_due(later), Some(("c", 2)));
+        assert_eq!(queue.pop_due(later), Some(("d", 1)));
+        assert!(queue.pop_due(later).is_none());
+    }
+}
@@ -0,0 +1,132 @@
+//! Snoozed messages, shown again later
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::gotify;
+
+/// Message waiting to be shown again
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Snoozed {
+    /// When to show it again
+    until: SystemTime,
+    /// The message
+    message: gotify::Message,
+}
+
+/// Snoozed messages, persisted so that they survive restarts
+pub(crate) struct Queue {
+    /// Snoozed messages, in no particular order
+    entries: Vec<Snoozed>,
+    /// Persistence file
+    filepath: PathBuf,
+}
+
+impl Queue {
+    /// Load queue from file, or create empty one
+    pub(crate) fn load(filepath: PathBuf) -> Self {
+        let entries = match Self::read(&filepath) {
+            Ok(entries) => entries,
+            Err(err) => {
+                if filepath.exists() {
+                    log::warn!("Failed to load snoozed messages {filepath:?}: {err:?}");
+                }
+                Vec::new()
+            }
+        };
+        log::debug!(
+            "Loaded {} snoozed message(s) from {filepath:?}",
+            entries.len()
+        );
+        Self { entries, filepath }
+    }
+
+    /// Read and parse queue file
+    fn read(filepath: &Path) -> anyhow::Result<Vec<Snoozed>> {
+        let json_data = fs::read_to_string(filepath)?;
+        Ok(serde_json::from_str(&json_data)?)
+    }
+
+    /// Write queue to file, a failure only means snoozes are lost on restart
+    fn save(&self) {
+        let res = serde_json::to_vec(&self.entries)
+            .map_err(anyhow::Error::from)
+            .and_then(|data| Ok(fs::write(&self.filepath, data)?));
+        if let Err(err) = res {
+            log::warn!(
+                "Failed to save snoozed messages to {:?}: {err:?}",
+                self.filepath
+            );
+        }
+    }
+
+    /// Snooze message for the given duration
+    pub(crate) fn push(
+        &mut self,
+        message: gotify::Message,
+        duration: Duration,
+    ) -> anyhow::Result<()> {
+        let until = SystemTime::now()
+            .checked_add(duration)
+            .ok_or_else(|| anyhow::anyhow!("Snooze duration {duration:?} is too long"))?;
+        log::info!("Snoozing message {} for {duration:?}", message.id);
+        self.entries.push(Snoozed { until, message });
+        self.save();
+        Ok(())
+    }
+
+    /// Remove and return a message whose snooze has ended
+    pub(crate) fn pop_due(&mut self, now: SystemTime) -> Option<gotify::Message> {
+        let idx = self.entries.iter().position(|e| e.until <= now)?;
+        let snoozed = self.entries.swap_remove(idx);
+        self.save();
+        Some(snoozed.message)
+    }
+
+    /// Time when the next snooze ends, if any
+    pub(crate) fn next_due(&self) -> Option<SystemTime> {
+        self.entries.iter().map(|e| e.until).min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn snooze_queue() {
+        let filepath = env::temp_dir().join(format!(
+            "{}-snoozed-{}.json",
+            env!("CARGO_PKG_NAME"),
+            std::process::id()
+        ));
+        let mut queue = Queue::load(filepath.clone());
+        assert!(queue.next_due().is_none());
+        assert!(
+            queue
+                .push(gotify::test_message(12, 1, 5), Duration::MAX)
+                .is_err()
+        );
+        queue
+            .push(gotify::test_message(12, 1, 5), Duration::from_secs(60))
+            .unwrap();
+        let now = SystemTime::now();
+        assert!(queue.pop_due(now).is_none());
+        assert!(queue.next_due().unwrap() > now);
+
+        let mut loaded = Queue::load(filepath.clone());
+        assert_eq!(loaded.next_due(), queue.next_due());
+        assert_eq!(
+            loaded.pop_due(now + Duration::from_secs(60)).map(|m| m.id),
+            Some(12)
+        );
+        assert!(loaded.next_due().is_none());
+        assert!(Queue::load(filepath.clone()).next_due().is_none());
+        fs::remove_file(&filepath).unwrap();
+    }
+}
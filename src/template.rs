@@ -0,0 +1,287 @@
+//! Notification summary & body templates
+
+use jiff::{Timestamp, tz::TimeZone};
+
+use crate::gotify;
+
+/// Default summary template
+pub(crate) const DEFAULT_SUMMARY: &str = "{title}";
+
+/// Default body template
+pub(crate) const DEFAULT_BODY: &str = "{text}\n{age}";
+
+/// Message age below which it is not considered caught up, and has no relative time
+const MIN_AGE_SECS: i64 = 60;
+
+/// Maximum number of characters of a rendered summary or body, longer ones are truncated
+const MAX_CHARS: usize = 2000;
+
+/// Render template, replacing placeholders with message values.
+/// Unknown placeholders are kept as is, and trailing whitespace is removed
+pub(crate) fn render(
+    template: &str,
+    msg: &gotify::Message,
+    server: &str,
+    now: Timestamp,
+    tz: &TimeZone,
+) -> String {
+    let value = |name: &str| -> Option<String> {
+        let value = match name {
+            "app" => app_name(msg),
+            "title" => msg.title.clone(),
+            "text" => msg.text.clone(),
+            "priority" => msg.priority.to_string(),
+            "date" => msg.date.parse::<Timestamp>().map_or_else(
+                |_| msg.date.clone(),
+                |ts| {
+                    ts.to_zoned(tz.clone())
+                        .strftime("%Y-%m-%d %H:%M")
+                        .to_string()
+                },
+            ),
+            "age" => msg
+                .date
+                .parse::<Timestamp>()
+                .map(|ts| relative_age(now.as_second().saturating_sub(ts.as_second())))
+                .unwrap_or_default(),
+            "server" => server.to_owned(),
+            _ => return None,
+        };
+        Some(value)
+    };
+
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some((before, after)) = rest.split_once('{') {
+        rendered.push_str(before);
+        if let Some((value, tail)) = after
+            .split_once('}')
+            .and_then(|(name, tail)| Some((value(name)?, tail)))
+        {
+            rendered.push_str(&value);
+            rest = tail;
+        } else {
+            // Not a placeholder, keep brace as is
+            rendered.push('{');
+            rest = after;
+        }
+    }
+    rendered.push_str(rest);
+    rendered.truncate(rendered.trim_end().len());
+    rendered
+}
+
+/// Name of the message app, or its id if the name is unknown
+pub(crate) fn app_name(msg: &gotify::Message) -> String {
+    msg.app_name
+        .clone()
+        .unwrap_or_else(|| format!("App {}", msg.appid))
+}
+
+/// Remove control characters other than line breaks and tabs, and truncate text that is too long.
+/// Markup is not cut inside a tag or entity
+pub(crate) fn sanitize(text: &str, markup: bool) -> String {
+    let mut sanitized: String = text
+        .chars()
+        .filter(|c| !c.is_control() || matches!(c, '\n' | '\t'))
+        .take(MAX_CHARS + 1)
+        .collect();
+    if let Some((idx, _)) = sanitized.char_indices().nth(MAX_CHARS) {
+        sanitized.truncate(idx);
+        if markup {
+            sanitized.truncate(complete_markup_len(&sanitized));
+        }
+        sanitized.push('…');
+    }
+    sanitized
+}
+
+/// Length of markup without its trailing incomplete tag or entity, if any
+fn complete_markup_len(markup: &str) -> usize {
+    let tag_start = markup
+        .rsplit_once('<')
+        .filter(|(_, tail)| !tail.contains('>'))
+        .map(|(head, _)| head.len());
+    let entity_start = markup
+        .rsplit_once('&')
+        .filter(|(_, tail)| tail.chars().all(|c| c.is_ascii_alphanumeric() || c == '#'))
+        .map(|(head, _)| head.len());
+    tag_start
+        .into_iter()
+        .chain(entity_start)
+        .min()
+        .unwrap_or(markup.len())
+}
+
+/// Escape text so that notification servers supporting body markup show it as is
+pub(crate) fn escape_markup(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Remove markup tags, and decode entities, for notification servers showing text as is.
+/// Unterminated tags are removed too
+pub(crate) fn strip_markup(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some((before, after)) = rest.split_once('<') {
+        stripped.push_str(before);
+        rest = after.split_once('>').map_or("", |(_, tail)| tail);
+    }
+    stripped.push_str(rest);
+    // Ampersand last, so that escaped entities are not decoded twice
+    stripped
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Remove image tags from markup, for notification servers not showing them
+pub(crate) fn strip_images(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some((before, after)) = rest.split_once('<') {
+        stripped.push_str(before);
+        if let Some((tag, tail)) = after.split_once('>') {
+            let is_img = tag
+                .split(|c: char| c.is_whitespace() || c == '/')
+                .next()
+                .is_some_and(|name| name.eq_ignore_ascii_case("img"));
+            if !is_img {
+                stripped.push('<');
+                stripped.push_str(tag);
+                stripped.push('>');
+            }
+            rest = tail;
+        } else {
+            stripped.push('<');
+            rest = after;
+        }
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+/// Human readable message age, empty for recent messages
+fn relative_age(secs: i64) -> String {
+    if secs < MIN_AGE_SECS {
+        String::new()
+    } else if secs < 60 * 60 {
+        format!("{} min ago", secs / 60)
+    } else if secs < 24 * 60 * 60 {
+        format!("{} h ago", secs / (60 * 60))
+    } else {
+        format!("{} day(s) ago", secs / (24 * 60 * 60))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_placeholders() {
+        let mut msg = gotify::Message {
+            text: "Disk full".to_owned(),
+            title: "Job failed".to_owned(),
+            date: "2024-01-01T10:00:00+01:00".to_owned(),
+            ..gotify::test_message(12, 3, 8)
+        };
+        let now: Timestamp = "2024-01-01T11:30:00Z".parse().unwrap();
+        let tz = TimeZone::UTC;
+
+        assert_eq!(
+            render("[{app}] {title}", &msg, "gotify.example.com", now, &tz),
+            "[App 3] Job failed"
+        );
+        msg.app_name = Some("Backups".to_owned());
+        assert_eq!(
+            render("[{app}] {title}", &msg, "gotify.example.com", now, &tz),
+            "[Backups] Job failed"
+        );
+        assert_eq!(
+            render(
+                "{text}\n{priority} {date} {server} {age}",
+                &msg,
+                "gotify.example.com",
+                now,
+                &tz
+            ),
+            "Disk full\n8 2024-01-01 09:00 gotify.example.com 2 h ago"
+        );
+        assert_eq!(
+            render(
+                "{text}\n{age}",
+                &msg,
+                "",
+                "2024-01-01T09:00:30Z".parse().unwrap(),
+                &tz
+            ),
+            "Disk full"
+        );
+        assert_eq!(
+            render("{unknown} {title} {", &msg, "", now, &tz),
+            "{unknown} Job failed {"
+        );
+    }
+
+    #[test]
+    fn sanitize_text() {
+        assert_eq!(sanitize("a\u{1b}[31mb\r\n\tc\0", false), "a[31mb\n\tc");
+        let long = "é".repeat(MAX_CHARS + 10);
+        let truncated = sanitize(&long, false);
+        assert_eq!(truncated.chars().count(), MAX_CHARS + 1);
+        assert!(truncated.ends_with("é…"));
+        let exact = "é".repeat(MAX_CHARS);
+        assert_eq!(sanitize(&exact, false), exact);
+
+        // Markup is not cut inside a tag or entity
+        let padding = "a".repeat(MAX_CHARS - 5);
+        for (tail, kept) in [
+            ("<b>bold</b>", "<b>bo"),
+            ("x<a href=\"y\">", "x"),
+            ("x&amp;y", "x"),
+            ("a & bc", "a & b"),
+        ] {
+            assert_eq!(
+                sanitize(&format!("{padding}{tail}"), true),
+                format!("{padding}{kept}…")
+            );
+        }
+        assert_eq!(
+            escape_markup(r#"<a href="x">Tom & Jerry</a>"#),
+            r#"&lt;a href="x"&gt;Tom &amp; Jerry&lt;/a&gt;"#
+        );
+    }
+
+    #[test]
+    fn strip_tags() {
+        assert_eq!(
+            strip_markup("<b>Tom</b> &amp; <i>Jerry</i> &lt;3 &amp;lt; <br"),
+            "Tom & Jerry <3 &lt; "
+        );
+        assert_eq!(
+            strip_images(r#"<b>Graph</b><img src="a.png"/> <IMG src="b.png"> 1 < 2"#),
+            "<b>Graph</b>  1 < 2"
+        );
+    }
+
+    #[test]
+    fn age() {
+        assert_eq!(relative_age(-5), "");
+        assert_eq!(relative_age(59), "");
+        assert_eq!(relative_age(60), "1 min ago");
+        assert_eq!(relative_age(2 * 60 * 60 + 1), "2 h ago");
+        assert_eq!(relative_age(3 * 24 * 60 * 60), "3 day(s) ago");
+    }
+}